@@ -1,9 +1,393 @@
-use crate::registry::CommentType;
+use crate::duplicates::hash_code_line;
+use crate::registry::{CommentType, FunctionRules, Scope};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, ErrorKind, Read};
 use std::path::Path;
 
-pub(crate) fn count_lines(path: &Path, comments: &CommentType) -> Result<u64, std::io::Error> {
+/// Files of at least this many bytes are read in chunks and split into lines from the raw
+/// bytes instead of through a `BufReader`.
+const CHUNKED_THRESHOLD: u64 = 16 * 1024 * 1024;
+/// Number of bytes read at once from a chunked file.
+const READ_CHUNK: usize = 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+enum ReadStrategy {
+    Buffered,
+    Chunked,
+}
+
+impl ReadStrategy {
+    fn for_size(len: u64, chunked_threshold: u64) -> Self {
+        // empty files have nothing to read in chunks
+        if len > 0 && len >= chunked_threshold {
+            ReadStrategy::Chunked
+        } else {
+            ReadStrategy::Buffered
+        }
+    }
+}
+
+/// Markers that code generators leave in the header of the files they write.
+const GENERATED_MARKERS: [&str; 3] = ["@generated", "DO NOT EDIT", "Code generated by"];
+/// Number of leading lines searched for a generated-file marker.
+const HEADER_LINES: u64 = 10;
+/// Average length of non-blank lines above which a file is considered minified.
+const MINIFIED_AVG_LINE_LEN: u64 = 300;
+
+/// Tag of a machine-readable license expression, see <https://spdx.dev>.
+const SPDX_TAG: &str = "SPDX-License-Identifier:";
+/// Number of leading lines searched for a license header.
+const LICENSE_HEADER_LINES: u64 = 50;
+/// Lowercase phrases of common license texts and the license they stand for, most specific first.
+const LICENSE_PHRASES: [(&str, &str); 10] = [
+    ("apache license, version 2.0", "Apache-2.0"),
+    ("apache license version 2.0", "Apache-2.0"),
+    ("gnu affero general public license", "AGPL"),
+    ("gnu lesser general public license", "LGPL"),
+    ("gnu general public license", "GPL"),
+    ("mozilla public license", "MPL"),
+    ("permission is hereby granted, free of charge", "MIT"),
+    ("mit license", "MIT"),
+    ("redistribution and use in source and binary forms", "BSD"),
+    (
+        "free and unencumbered software released into the public domain",
+        "Unlicense",
+    ),
+];
+
+/// Tab width used when a language does not configure one.
+pub(crate) const DEFAULT_TAB_WIDTH: u64 = 4;
+/// Comment markers searched for when a language does not configure its own.
+pub(crate) const DEFAULT_MARKERS: [&str; 5] = ["TODO", "FIXME", "HACK", "XXX", "SAFETY"];
+
+/// Per-language rules a file is analysed with.
+pub(crate) struct Rules<'a> {
+    pub(crate) comments: &'a CommentType,
+    /// Keywords and operators that each add a decision point when found on a code line.
+    pub(crate) decisions: &'a [String],
+    pub(crate) functions: Option<&'a FunctionRules>,
+    /// Columns a tab advances to, also the width of one indentation level.
+    pub(crate) tab_width: u64,
+    /// Lines longer than this many characters are recorded in `FileStats::long_lines`.
+    pub(crate) max_line_width: Option<u64>,
+    /// Words such as `TODO` recorded in `FileStats::markers` when found inside a comment.
+    pub(crate) markers: &'a [String],
+}
+
+impl<'a> From<&'a CommentType> for Rules<'a> {
+    fn from(comments: &'a CommentType) -> Self {
+        Self {
+            comments,
+            decisions: &[],
+            functions: None,
+            tab_width: DEFAULT_TAB_WIDTH,
+            max_line_width: None,
+            markers: &[],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct LongLine {
+    /// 1-based line number.
+    pub(crate) line: u64,
+    /// Length in characters.
+    pub(crate) length: u64,
+}
+
+/// Number of non-blank lines of each length, in characters.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct LineLengths(BTreeMap<u64, u64>);
+
+impl LineLengths {
+    fn add(&mut self, length: u64) {
+        *self.0.entry(length).or_default() += 1;
+    }
+
+    pub(crate) fn merge(&mut self, other: &LineLengths) {
+        for (length, count) in other.0.iter() {
+            *self.0.entry(*length).or_default() += count;
+        }
+    }
+
+    pub(crate) fn max(&self) -> u64 {
+        self.0.keys().next_back().copied().unwrap_or(0)
+    }
+
+    pub(crate) fn mean(&self) -> f64 {
+        let (sum, count) = self.0.iter().fold((0, 0), |(sum, count), (length, n)| {
+            (sum + length * n, count + n)
+        });
+        if count == 0 {
+            0.0
+        } else {
+            sum as f64 / count as f64
+        }
+    }
+
+    /// Nearest-rank percentile, `percent` in 1..=100.
+    pub(crate) fn percentile(&self, percent: u64) -> u64 {
+        let count: u64 = self.0.values().sum();
+        let rank = (count * percent).div_ceil(100);
+        let mut seen = 0;
+        for (length, n) in self.0.iter() {
+            seen += n;
+            if seen >= rank {
+                return *length;
+            }
+        }
+        0
+    }
+}
+
+/// Size of a set of lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub(crate) struct Volume {
+    /// Bytes in the file, line terminators included.
+    pub(crate) bytes: u64,
+    /// Unicode scalar values, line terminators included.
+    pub(crate) chars: u64,
+    /// Rough estimate of the tokens a language model would split the text into.
+    pub(crate) tokens: u64,
+}
+
+impl Volume {
+    fn add_line(&mut self, line: &str, len: usize) {
+        self.bytes += len as u64;
+        // terminators are ASCII, one char per byte
+        self.chars += line.chars().count() as u64 + (len - line.len()) as u64;
+        self.tokens += estimate_tokens(line);
+    }
+
+    fn merge(&mut self, other: &Volume) {
+        self.bytes += other.bytes;
+        self.chars += other.chars;
+        self.tokens += other.tokens;
+    }
+}
+
+/// Size of the code, comment and blank lines of a file or language.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub(crate) struct Volumes {
+    pub(crate) code: Volume,
+    pub(crate) comment: Volume,
+    pub(crate) blank: Volume,
+}
+
+impl Volumes {
+    pub(crate) fn merge(&mut self, other: &Volumes) {
+        self.code.merge(&other.code);
+        self.comment.merge(&other.comment);
+        self.blank.merge(&other.blank);
+    }
+
+    pub(crate) fn total(&self) -> Volume {
+        let mut total = self.code;
+        total.merge(&self.comment);
+        total.merge(&self.blank);
+        total
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Marker {
+    pub(crate) marker: String,
+    /// 1-based line number.
+    pub(crate) line: u64,
+    /// Comment text from the marker to the end of the comment.
+    pub(crate) text: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Function {
+    pub(crate) name: String,
+    /// 1-based line the function starts on.
+    pub(crate) line: u64,
+    /// Length in code lines, including the signature.
+    pub(crate) length: u64,
+}
+
+struct OpenFunction {
+    function: Function,
+    /// Brace depth or indentation the function was declared at.
+    level: usize,
+    /// Whether the opening brace of the body has been seen.
+    entered: bool,
+}
+
+/// Follows function scopes across the code lines of a file.
+#[derive(Default)]
+struct FunctionTracker {
+    open: Vec<OpenFunction>,
+    depth: usize,
+    done: Vec<Function>,
+}
+
+impl FunctionTracker {
+    fn visit(&mut self, line_no: u64, code: &str, indent: u64, rules: &FunctionRules) {
+        if rules.scope == Scope::Indent {
+            let indent = indent as usize;
+            while self.open.last().is_some_and(|f| indent <= f.level) {
+                self.close();
+            }
+            self.start(line_no, code, rules, indent);
+            for open in self.open.iter_mut() {
+                open.function.length += 1;
+            }
+            return;
+        }
+
+        self.start(line_no, code, rules, self.depth);
+        for open in self.open.iter_mut() {
+            open.function.length += 1;
+        }
+        for c in outside_literals(code) {
+            match c {
+                '{' => {
+                    self.depth += 1;
+                    if let Some(open) = self.open.last_mut() {
+                        open.entered = true;
+                    }
+                }
+                '}' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    while self
+                        .open
+                        .last()
+                        .is_some_and(|f| f.entered && self.depth <= f.level)
+                    {
+                        self.close();
+                    }
+                }
+                // a signature without a body is only a declaration
+                ';' if self
+                    .open
+                    .last()
+                    .is_some_and(|f| !f.entered && self.depth == f.level) =>
+                {
+                    self.open.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn start(&mut self, line_no: u64, code: &str, rules: &FunctionRules, level: usize) {
+        if let Some(caps) = rules.start.captures(code) {
+            let name = caps
+                .name("name")
+                .map_or_else(String::new, |m| m.as_str().to_string());
+            self.open.push(OpenFunction {
+                function: Function {
+                    name,
+                    line: line_no,
+                    length: 0,
+                },
+                level,
+                entered: rules.scope == Scope::Indent,
+            });
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(open) = self.open.pop() {
+            self.done.push(open.function);
+        }
+    }
+
+    /// Closes the functions still open at the end of the file.
+    fn finish(mut self) -> Vec<Function> {
+        while !self.open.is_empty() {
+            self.close();
+        }
+        self.done.sort_by_key(|f| f.line);
+        self.done
+    }
+}
+
+/// Characters of a line of code that are not inside a string or character literal.
+/// A string left open runs to the end of the line.
+fn outside_literals(code: &str) -> impl Iterator<Item = char> + '_ {
+    fn skip_to_quote(chars: &mut std::str::Chars, quote: char) {
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                chars.next();
+            } else if c == quote {
+                break;
+            }
+        }
+    }
+    let mut chars = code.chars();
+    std::iter::from_fn(move || {
+        loop {
+            match chars.next()? {
+                '"' => skip_to_quote(&mut chars, '"'),
+                // a character literal, not a lifetime such as `'a`
+                '\'' if chars.as_str().starts_with('\\')
+                    || chars.as_str().chars().nth(1) == Some('\'') =>
+                {
+                    skip_to_quote(&mut chars, '\'');
+                }
+                c => return Some(c),
+            }
+        }
+    })
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct FileStats {
+    pub(crate) code: u64,
+    /// Number of decision points on code lines, a cheap stand-in for cyclomatic complexity.
+    pub(crate) complexity: u64,
+    pub(crate) functions: Vec<Function>,
+    /// Deepest indentation level of a code line.
+    pub(crate) max_depth: u64,
+    /// Sum of the indentation levels of all code lines.
+    pub(crate) depth_sum: u64,
+    pub(crate) line_lengths: LineLengths,
+    pub(crate) long_lines: Vec<LongLine>,
+    pub(crate) markers: Vec<Marker>,
+    /// SPDX expression or license family named in the leading comment block.
+    pub(crate) license: Option<String>,
+    /// Line number and normalised hash of every code line, comments removed.
+    pub(crate) code_hashes: Vec<(u64, u64)>,
+    pub(crate) volume: Volumes,
+    lines: u64,
+    non_blank: u64,
+    non_blank_bytes: u64,
+    generated_marker: bool,
+}
+
+impl FileStats {
+    pub(crate) fn mean_depth(&self) -> f64 {
+        if self.code == 0 {
+            0.0
+        } else {
+            self.depth_sum as f64 / self.code as f64
+        }
+    }
+
+    /// True when the file carries a generator marker in its header or looks minified.
+    pub(crate) fn looks_generated(&self) -> bool {
+        self.generated_marker
+            || (self.non_blank > 0 && self.non_blank_bytes / self.non_blank > MINIFIED_AVG_LINE_LEN)
+    }
+}
+
+pub(crate) fn count_lines<'a>(
+    path: &Path,
+    rules: impl Into<Rules<'a>>,
+) -> Result<FileStats, std::io::Error> {
+    count_lines_with(path, &rules.into(), CHUNKED_THRESHOLD)
+}
+
+fn count_lines_with(
+    path: &Path,
+    rules: &Rules,
+    chunked_threshold: u64,
+) -> Result<FileStats, std::io::Error> {
     if !path.is_file() {
         return Err(std::io::Error::new(
             std::io::ErrorKind::NotFound,
@@ -11,13 +395,326 @@ pub(crate) fn count_lines(path: &Path, comments: &CommentType) -> Result<u64, st
         ));
     }
     let mut inside_block = false;
-    let cnt = BufReader::new(File::open(path)?)
-        .lines()
-        .map_while(Result::ok)
-        .filter(|line| is_line_of_code(line, &mut inside_block, comments))
-        .count() as u64;
+    let mut stats = FileStats::default();
+    let mut functions = FunctionTracker::default();
+    let mut header = String::new();
+    let mut in_header = true;
+    for_each_line(path, chunked_threshold, |line, len| {
+        if stats.lines < HEADER_LINES && !stats.generated_marker {
+            stats.generated_marker = GENERATED_MARKERS.iter().any(|m| line.contains(m));
+        }
+        stats.lines += 1;
+        if !line.trim().is_empty() {
+            let length = line.chars().count() as u64;
+            stats.non_blank += 1;
+            stats.non_blank_bytes += line.len() as u64;
+            stats.line_lengths.add(length);
+            if rules.max_line_width.is_some_and(|width| length > width) {
+                stats.long_lines.push(LongLine {
+                    line: stats.lines,
+                    length,
+                });
+            }
+        }
+        let starts_inside_block = inside_block;
+        let is_code = is_line_of_code(line, &mut inside_block, rules.comments);
+        // the header ends at the first code line, a shebang does not count
+        if is_code && !(stats.lines == 1 && line.starts_with("#!")) {
+            in_header = false;
+        }
+        if is_code {
+            stats.volume.code.add_line(line, len);
+        } else if line.trim().is_empty() {
+            stats.volume.blank.add_line(line, len);
+        } else {
+            stats.volume.comment.add_line(line, len);
+        }
+        if in_header || !rules.markers.is_empty() {
+            for comment in comment_segments(line.trim(), starts_inside_block, rules.comments) {
+                find_markers(comment, stats.lines, rules.markers, &mut stats.markers);
+                if in_header {
+                    header.push_str(comment);
+                    header.push('\n');
+                }
+            }
+        }
+        if stats.lines >= LICENSE_HEADER_LINES {
+            in_header = false;
+        }
+        if is_code {
+            let code = strip_line_comment(line, rules.comments);
+            let indent = indent_columns(code, rules.tab_width);
+            let depth = indent / rules.tab_width;
+            stats.code += 1;
+            if !rules.decisions.is_empty() {
+                let code = strip_comments(line, starts_inside_block, rules.comments);
+                stats.complexity += count_decisions(&code, rules.decisions);
+            }
+            stats.max_depth = stats.max_depth.max(depth);
+            stats.depth_sum += depth;
+            stats.code_hashes.push((stats.lines, hash_code_line(code)));
+            if let Some(function_rules) = rules.functions {
+                functions.visit(stats.lines, code, indent, function_rules);
+            }
+        }
+    })?;
+    stats.functions = functions.finish();
+    stats.license = detect_license(&header);
+
+    Ok(stats)
+}
+
+/// Calls `visit` for every line of the file, without the line terminator, and the
+/// number of bytes the line takes in the file, terminator included.
+/// Reading stops at the first line that is not valid UTF-8.
+fn for_each_line(
+    path: &Path,
+    chunked_threshold: u64,
+    mut visit: impl FnMut(&str, usize),
+) -> Result<(), std::io::Error> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    if ReadStrategy::for_size(len, chunked_threshold) == ReadStrategy::Chunked {
+        return scan_chunked(file, READ_CHUNK, visit);
+    }
+    let mut reader = BufReader::new(file);
+    let mut buf = String::new();
+    while let Ok(len) = reader.read_line(&mut buf) {
+        if len == 0 {
+            break;
+        }
+        let line = buf.strip_suffix('\n').unwrap_or(&buf);
+        visit(line.strip_suffix('\r').unwrap_or(line), len);
+        buf.clear();
+    }
+    Ok(())
+}
+
+/// Splits the bytes of `reader` into lines the same way `BufRead::lines` does, reading
+/// `chunk` bytes at a time into one buffer that only grows for lines longer than that.
+///
+/// A file truncated during the scan ends early, reads past its new end return nothing.
+fn scan_chunked(
+    mut reader: impl Read,
+    chunk: usize,
+    mut visit: impl FnMut(&str, usize),
+) -> Result<(), std::io::Error> {
+    let mut buf = vec![0; chunk];
+    let mut filled = 0; // bytes of `buf` holding the start of the current line and beyond
+
+    loop {
+        if filled == buf.len() {
+            // the current line fills the whole buffer
+            buf.resize(buf.len() + chunk, 0);
+        }
+        let read = match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let mut start = 0; // first byte of the current line
+        let mut scanned = filled; // bytes already searched for a newline
+        filled += read;
+        while let Some(idx) = buf[scanned..filled].iter().position(|&b| b == b'\n') {
+            let line_end = scanned + idx;
+            if !visit_bytes(&buf[start..line_end], line_end + 1 - start, &mut visit) {
+                return Ok(());
+            }
+            start = line_end + 1;
+            scanned = start;
+        }
+        buf.copy_within(start..filled, 0);
+        filled -= start;
+    }
+    if filled > 0 {
+        visit_bytes(&buf[..filled], filled, &mut visit);
+    }
+    Ok(())
+}
+
+fn visit_bytes(line: &[u8], len: usize, visit: &mut impl FnMut(&str, usize)) -> bool {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    match std::str::from_utf8(line) {
+        Ok(line) => {
+            visit(line, len);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Width of the leading whitespace, with tabs advancing to the next tab stop.
+fn indent_columns(line: &str, tab_width: u64) -> u64 {
+    let mut columns = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => columns += 1,
+            '\t' => columns += tab_width - columns % tab_width,
+            _ => break,
+        }
+    }
+    columns
+}
+
+/// Cuts a trailing line comment off a line of code.
+fn strip_line_comment<'a>(line: &'a str, comments: &CommentType) -> &'a str {
+    comments
+        .line
+        .iter()
+        .filter_map(|marker| line.find(marker.as_str()))
+        .min()
+        .map_or(line, |idx| &line[..idx])
+}
+
+/// The code of a line, with each comment and its delimiters replaced by a space,
+/// given whether the line starts inside a block comment.
+fn strip_comments(line: &str, inside_block: bool, comments: &CommentType) -> String {
+    fn without_delimiters<'a>(mut code: &'a str, comments: &CommentType) -> &'a str {
+        if let Some(block) = &comments.block {
+            code = code.strip_prefix(block.close.as_str()).unwrap_or(code);
+            code = code.strip_suffix(block.open.as_str()).unwrap_or(code);
+        }
+        comments
+            .line
+            .iter()
+            .find_map(|marker| code.strip_suffix(marker.as_str()))
+            .unwrap_or(code)
+    }
+    let mut code = String::new();
+    let mut end = 0; // first byte after the last comment
+    for segment in comment_segments(line, inside_block, comments) {
+        let start = segment.as_ptr() as usize - line.as_ptr() as usize;
+        code.push_str(without_delimiters(&line[end..start], comments));
+        code.push(' ');
+        end = start + segment.len();
+    }
+    code.push_str(without_delimiters(&line[end..], comments));
+    code
+}
+
+/// Returns the parts of a line that lie inside comments, given whether the line
+/// starts inside a block comment.
+fn comment_segments<'a>(line: &'a str, inside_block: bool, comments: &CommentType) -> Vec<&'a str> {
+    let mut segments = Vec::new();
+    let mut rest = line;
+    if let Some(block) = comments.block.as_ref().filter(|_| inside_block) {
+        match rest.find(&block.close) {
+            Some(idx) => {
+                segments.push(&rest[..idx]);
+                rest = &rest[idx + block.close.len()..];
+            }
+            None => return vec![line],
+        }
+    }
+    loop {
+        let line_start = comments
+            .line
+            .iter()
+            .filter_map(|marker| rest.find(marker.as_str()).map(|idx| (idx, marker.len())))
+            .min();
+        let block_start = comments
+            .block
+            .as_ref()
+            .and_then(|block| rest.find(&block.open).map(|idx| (idx, block)));
+        match (line_start, block_start) {
+            (Some((idx, len)), block) if block.is_none_or(|(open, _)| idx < open) => {
+                segments.push(&rest[idx + len..]);
+                break;
+            }
+            (_, Some((idx, block))) => {
+                rest = &rest[idx + block.open.len()..];
+                match rest.find(&block.close) {
+                    Some(end) => {
+                        segments.push(&rest[..end]);
+                        rest = &rest[end + block.close.len()..];
+                    }
+                    None => {
+                        segments.push(rest);
+                        break;
+                    }
+                }
+            }
+            _ => break,
+        }
+    }
+    segments
+}
+
+/// Finds the license named in the comment text of a file header, an SPDX tag wins over phrases.
+fn detect_license(header: &str) -> Option<String> {
+    if let Some(idx) = header.find(SPDX_TAG) {
+        let expression = header[idx + SPDX_TAG.len()..]
+            .lines()
+            .next()
+            .unwrap_or("")
+            .trim();
+        if !expression.is_empty() {
+            return Some(expression.to_string());
+        }
+    }
+    let text = header
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    LICENSE_PHRASES
+        .iter()
+        .find(|(phrase, _)| text.contains(phrase))
+        .map(|(_, license)| license.to_string())
+}
+
+fn find_markers(comment: &str, line_no: u64, markers: &[String], found: &mut Vec<Marker>) {
+    let mut hits: Vec<(usize, &String)> = markers
+        .iter()
+        .flat_map(|marker| token_positions(comment, marker).map(move |idx| (idx, marker)))
+        .collect();
+    hits.sort();
+    found.extend(hits.into_iter().map(|(idx, marker)| Marker {
+        marker: marker.clone(),
+        line: line_no,
+        text: comment[idx..].trim_end().to_string(),
+    }));
+}
 
-    Ok(cnt)
+/// Rough token count: one token per four characters of a word, one per other symbol.
+fn estimate_tokens(line: &str) -> u64 {
+    let mut tokens = 0;
+    let mut word = 0;
+    for c in line.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            word += 1;
+            continue;
+        }
+        tokens += u64::div_ceil(word, 4);
+        word = 0;
+        if !c.is_whitespace() {
+            tokens += 1;
+        }
+    }
+    tokens + u64::div_ceil(word, 4)
+}
+
+fn count_decisions(code: &str, decisions: &[String]) -> u64 {
+    decisions.iter().map(|token| count_token(code, token)).sum()
+}
+
+fn count_token(code: &str, token: &str) -> u64 {
+    token_positions(code, token).count() as u64
+}
+
+/// Byte offsets of `token` in `text`, keywords only where they stand as a whole word.
+fn token_positions<'t>(text: &'t str, token: &'t str) -> impl Iterator<Item = usize> + 't {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let is_keyword = token.chars().all(is_ident);
+    text.match_indices(token)
+        .map(|(idx, _)| idx)
+        .filter(move |idx| {
+            !is_keyword
+                || (!text[..*idx].ends_with(is_ident)
+                    && !text[idx + token.len()..].starts_with(is_ident))
+        })
 }
 
 fn is_line_of_code(line: &str, is_inside_block: &mut bool, comment_type: &CommentType) -> bool {
@@ -75,7 +772,7 @@ fn is_block_comment(line: &str, is_inside_block: &mut bool, comment_type: &Comme
             }
             code_present = true;
         }
-        start += 1;
+        start += rest.chars().next().map_or(1, char::len_utf8);
     }
     !code_present
 }
@@ -99,7 +796,7 @@ mod tests {
                 }),
             };
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 0);
+            assert_eq!(res.unwrap().code, 0);
         }
 
         #[test]
@@ -140,7 +837,7 @@ mod tests {
             .unwrap();
 
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 0);
+            assert_eq!(res.unwrap().code, 0);
         }
         #[test]
         fn single_line_comments_with_code() {
@@ -169,7 +866,7 @@ mod tests {
             .unwrap();
 
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 4);
+            assert_eq!(res.unwrap().code, 4);
         }
 
         #[test]
@@ -195,7 +892,7 @@ mod tests {
             .unwrap();
 
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 3);
+            assert_eq!(res.unwrap().code, 3);
         }
 
         #[test]
@@ -217,7 +914,7 @@ mod tests {
             .unwrap();
 
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 2);
+            assert_eq!(res.unwrap().code, 2);
         }
 
         #[test]
@@ -239,7 +936,7 @@ mod tests {
             )
             .unwrap();
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 0);
+            assert_eq!(res.unwrap().code, 0);
         }
 
         #[test]
@@ -262,7 +959,7 @@ mod tests {
             )
             .unwrap();
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 1);
+            assert_eq!(res.unwrap().code, 1);
         }
         #[test]
         fn block_comments_multi_line_code_before() {
@@ -284,7 +981,7 @@ mod tests {
             )
             .unwrap();
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 1);
+            assert_eq!(res.unwrap().code, 1);
         }
         #[test]
         fn block_comments_single_line_no_code() {
@@ -304,7 +1001,7 @@ mod tests {
             )
             .unwrap();
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 0);
+            assert_eq!(res.unwrap().code, 0);
         }
         #[test]
         fn block_comments_single_line_code_before() {
@@ -324,7 +1021,7 @@ mod tests {
             )
             .unwrap();
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 1);
+            assert_eq!(res.unwrap().code, 1);
         }
         #[test]
         fn block_comments_single_line_code_after() {
@@ -344,7 +1041,7 @@ mod tests {
             )
             .unwrap();
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 1);
+            assert_eq!(res.unwrap().code, 1);
         }
         #[test]
         fn block_comments_single_line_interleaved() {
@@ -364,7 +1061,7 @@ mod tests {
             )
             .unwrap();
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 1);
+            assert_eq!(res.unwrap().code, 1);
         }
         #[test]
         fn block_comments_no_end() {
@@ -385,7 +1082,7 @@ mod tests {
             )
             .unwrap();
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 0);
+            assert_eq!(res.unwrap().code, 0);
         }
 
         #[test]
@@ -427,7 +1124,7 @@ mod tests {
             )
             .unwrap();
             let res = count_lines(file.path(), &comments);
-            assert_eq!(res.unwrap(), 5);
+            assert_eq!(res.unwrap().code, 5);
         }
 
         // TODO:
@@ -457,7 +1154,7 @@ mod tests {
         //    )
         //    .unwrap();
         //    let res = count_lines(file.path(), &comments);
-        //    assert_eq!(res.unwrap(), 5);
+        //    assert_eq!(res.unwrap().code, 5);
         //}
         //#[test]
         //fn windows_newlines() {
@@ -471,9 +1168,651 @@ mod tests {
         //    };
         //    write!(file, "\r\ncode\n//text\r\n").unwrap();
         //    let res = count_lines(file.path(), &comments);
-        //    assert_eq!(res.unwrap(), 0);
+        //    assert_eq!(res.unwrap().code, 0);
         //}
     }
+    mod generated {
+        use crate::analysis::count_lines;
+        use crate::registry::CommentType;
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        fn comments() -> CommentType {
+            CommentType {
+                line: vec!["//".to_string()],
+                block: None,
+            }
+        }
+
+        #[test]
+        fn header_marker() {
+            let mut file = NamedTempFile::new().unwrap();
+            write!(
+                file,
+                "// Code generated by protoc-gen-go. DO NOT EDIT.\ncode\n"
+            )
+            .unwrap();
+            let res = count_lines(file.path(), &comments()).unwrap();
+            assert!(res.looks_generated());
+        }
+
+        #[test]
+        fn marker_past_header_is_ignored() {
+            let mut file = NamedTempFile::new().unwrap();
+            writeln!(file, "{}// @generated", "code\n".repeat(20)).unwrap();
+            let res = count_lines(file.path(), &comments()).unwrap();
+            assert!(!res.looks_generated());
+        }
+
+        #[test]
+        fn minified() {
+            let mut file = NamedTempFile::new().unwrap();
+            write!(file, "{}\n\n", "var a=1;".repeat(100)).unwrap();
+            let res = count_lines(file.path(), &comments()).unwrap();
+            assert!(res.looks_generated());
+            assert_eq!(res.code, 1);
+        }
+
+        #[test]
+        fn hand_written() {
+            let mut file = NamedTempFile::new().unwrap();
+            write!(file, "// DO NOT\n// EDIT\ncode\n").unwrap();
+            let res = count_lines(file.path(), &comments()).unwrap();
+            assert!(!res.looks_generated());
+        }
+    }
+    mod complexity {
+        use crate::analysis::{Rules, count_lines, count_token};
+        use crate::registry::{Block, CommentType};
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        #[test]
+        fn keywords_match_whole_words() {
+            assert_eq!(count_token("if x { iffy(); } else if y {", "if"), 2);
+            assert_eq!(count_token("for_each(|x| x.format())", "for"), 0);
+            assert_eq!(count_token("a && b && !c", "&&"), 2);
+        }
+
+        #[test]
+        fn only_code_lines_count() {
+            let mut file = NamedTempFile::new().unwrap();
+            let comments = CommentType {
+                line: vec!["//".to_string()],
+                block: Some(Block {
+                    open: "/*".to_string(),
+                    close: "*/".to_string(),
+                }),
+            };
+            let decisions = ["if", "while", "&&", "?"].map(String::from);
+            write!(
+                file,
+                r#"// if this && that
+                /* while
+                   if */
+                if a && b {{ // while
+                    x = c ? 1 : 2;
+                }}
+                while (d) {{}}
+                "#
+            )
+            .unwrap();
+
+            let rules = Rules {
+                decisions: &decisions,
+                ..Rules::from(&comments)
+            };
+            let res = count_lines(file.path(), rules).unwrap();
+            assert_eq!(res.code, 4);
+            assert_eq!(res.complexity, 4);
+        }
+
+        #[test]
+        fn block_comments_on_code_lines_do_not_count() {
+            let mut file = NamedTempFile::new().unwrap();
+            let comments = CommentType {
+                line: vec!["//".to_string()],
+                block: Some(Block {
+                    open: "/*".to_string(),
+                    close: "*/".to_string(),
+                }),
+            };
+            let decisions = ["if", "while", "&&"].map(String::from);
+            write!(
+                file,
+                "x = 1; /* if a && b */\ny = /* while */ a && b; /* if\nwhile */ if c {{}}\n"
+            )
+            .unwrap();
+
+            let rules = Rules {
+                decisions: &decisions,
+                ..Rules::from(&comments)
+            };
+            let res = count_lines(file.path(), rules).unwrap();
+            assert_eq!(res.code, 3);
+            assert_eq!(res.complexity, 2);
+        }
+    }
+    mod functions {
+        use crate::analysis::{Function, Rules, count_lines};
+        use crate::registry::{Block, CommentType, FunctionRules, Scope};
+        use regex::Regex;
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        fn function(name: &str, line: u64, length: u64) -> Function {
+            Function {
+                name: name.to_string(),
+                line,
+                length,
+            }
+        }
+
+        #[test]
+        fn brace_scoped() {
+            let mut file = NamedTempFile::new().unwrap();
+            let comments = CommentType {
+                line: vec!["//".to_string()],
+                block: Some(Block {
+                    open: "/*".to_string(),
+                    close: "*/".to_string(),
+                }),
+            };
+            let functions = FunctionRules {
+                start: Regex::new(r"^\s*(pub\s+)?fn\s+(?P<name>\w+)").unwrap(),
+                scope: Scope::Braces,
+            };
+            write!(
+                file,
+                r#"fn short() {{ 1 }}
+trait T {{
+    fn declared(&self);
+}}
+pub fn outer(
+    a: u32, // }}
+) -> u32 {{
+    /* {{ */
+    fn inner() {{
+    }}
+
+    a
+}}
+"#
+            )
+            .unwrap();
+
+            let rules = Rules {
+                functions: Some(&functions),
+                ..Rules::from(&comments)
+            };
+            let res = count_lines(file.path(), rules).unwrap();
+            assert_eq!(
+                res.functions,
+                vec![
+                    function("short", 1, 1),
+                    function("outer", 5, 7),
+                    function("inner", 9, 2)
+                ]
+            );
+        }
+
+        #[test]
+        fn braces_in_literals() {
+            let mut file = NamedTempFile::new().unwrap();
+            let comments = CommentType {
+                line: vec!["//".to_string()],
+                block: None,
+            };
+            let functions = FunctionRules {
+                start: Regex::new(r"^\s*fn\s+(?P<name>\w+)").unwrap(),
+                scope: Scope::Braces,
+            };
+            write!(
+                file,
+                r#"fn open() {{
+    let s = "{{ \" {{";
+    let c = '{{';
+}}
+fn quote<'a>(s: &'a str) -> char {{
+    let _ = "{{}}{{";
+    '\''
+}}
+fn after() {{
+}}
+"#
+            )
+            .unwrap();
+
+            let rules = Rules {
+                functions: Some(&functions),
+                ..Rules::from(&comments)
+            };
+            let res = count_lines(file.path(), rules).unwrap();
+            assert_eq!(
+                res.functions,
+                vec![
+                    function("open", 1, 4),
+                    function("quote", 5, 4),
+                    function("after", 9, 2)
+                ]
+            );
+        }
+
+        #[test]
+        fn indent_scoped() {
+            let mut file = NamedTempFile::new().unwrap();
+            let comments = CommentType {
+                line: vec!["#".to_string()],
+                block: None,
+            };
+            let functions = FunctionRules {
+                start: Regex::new(r"^\s*(async\s+)?def\s+(?P<name>\w+)").unwrap(),
+                scope: Scope::Indent,
+            };
+            write!(
+                file,
+                r#"import os
+
+def first(a,
+          b):
+    x = a
+
+# comment at column zero
+    return x
+
+class C:
+    def method(self):
+        def nested():
+            pass
+        return 1
+    y = 2
+def last(): pass"#
+            )
+            .unwrap();
+
+            let rules = Rules {
+                functions: Some(&functions),
+                ..Rules::from(&comments)
+            };
+            let res = count_lines(file.path(), rules).unwrap();
+            assert_eq!(
+                res.functions,
+                vec![
+                    function("first", 3, 4),
+                    function("method", 11, 4),
+                    function("nested", 12, 2),
+                    function("last", 16, 1)
+                ]
+            );
+        }
+    }
+    mod nesting {
+        use crate::analysis::{Rules, count_lines, indent_columns};
+        use crate::registry::CommentType;
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        #[test]
+        fn tabs_advance_to_tab_stop() {
+            assert_eq!(indent_columns("    code", 4), 4);
+            assert_eq!(indent_columns("\tcode", 4), 4);
+            assert_eq!(indent_columns("  \tcode", 4), 4);
+            assert_eq!(indent_columns("\t  \tcode", 8), 16);
+            assert_eq!(indent_columns("code  ", 4), 0);
+        }
+
+        #[test]
+        fn depth_of_code_lines() {
+            let mut file = NamedTempFile::new().unwrap();
+            let comments = CommentType {
+                line: vec!["#".to_string()],
+                block: None,
+            };
+            write!(
+                file,
+                "def f():\n\tif x:\n\t\t\t\t# deep comment\n\t\treturn 1\n  \treturn 2\n"
+            )
+            .unwrap();
+
+            let rules = Rules {
+                tab_width: 2,
+                ..Rules::from(&comments)
+            };
+            let res = count_lines(file.path(), rules).unwrap();
+            assert_eq!(res.code, 4);
+            assert_eq!(res.max_depth, 2);
+            assert_eq!(res.depth_sum, 5);
+        }
+    }
+    mod line_lengths {
+        use crate::analysis::{LineLengths, LongLine, Rules, count_lines};
+        use crate::registry::CommentType;
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        #[test]
+        fn summary() {
+            let mut lengths = LineLengths::default();
+            for length in 1..=20 {
+                lengths.add(length);
+            }
+            assert_eq!(lengths.max(), 20);
+            assert_eq!(lengths.mean(), 10.5);
+            assert_eq!(lengths.percentile(95), 19);
+            assert_eq!(lengths.percentile(100), 20);
+            assert_eq!(LineLengths::default().percentile(95), 0);
+        }
+
+        #[test]
+        fn merge() {
+            let mut a = LineLengths::default();
+            a.add(10);
+            a.add(30);
+            let mut b = LineLengths::default();
+            b.add(10);
+            a.merge(&b);
+            assert_eq!(a.max(), 30);
+            assert_eq!(a.percentile(50), 10);
+        }
+
+        #[test]
+        fn long_lines_over_width() {
+            let mut file = NamedTempFile::new().unwrap();
+            let comments = CommentType {
+                line: vec!["//".to_string()],
+                block: None,
+            };
+            write!(file, "short\n\n// a comment that is too long\nżółć żółć\n").unwrap();
+
+            let rules = Rules {
+                max_line_width: Some(8),
+                ..Rules::from(&comments)
+            };
+            let res = count_lines(file.path(), rules).unwrap();
+            assert_eq!(res.line_lengths.max(), 29);
+            assert_eq!(
+                res.long_lines,
+                vec![
+                    LongLine {
+                        line: 3,
+                        length: 29
+                    },
+                    LongLine { line: 4, length: 9 }
+                ]
+            );
+        }
+    }
+    mod markers {
+        use crate::analysis::{DEFAULT_MARKERS, Marker, Rules, comment_segments, count_lines};
+        use crate::registry::{Block, CommentType};
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        fn c_comments() -> CommentType {
+            CommentType {
+                line: vec!["//".to_string()],
+                block: Some(Block {
+                    open: "/*".to_string(),
+                    close: "*/".to_string(),
+                }),
+            }
+        }
+
+        #[test]
+        fn segments() {
+            let comments = c_comments();
+            assert_eq!(
+                comment_segments("code", false, &comments),
+                Vec::<&str>::new()
+            );
+            assert_eq!(comment_segments("a; // b", false, &comments), vec![" b"]);
+            assert_eq!(
+                comment_segments("a /* b */ c /* d", false, &comments),
+                vec![" b ", " d"]
+            );
+            assert_eq!(
+                comment_segments("b */ c // d", true, &comments),
+                vec!["b ", " d"]
+            );
+            assert_eq!(
+                comment_segments("still inside", true, &comments),
+                vec!["still inside"]
+            );
+        }
+
+        #[test]
+        fn only_in_comments() {
+            let mut file = NamedTempFile::new().unwrap();
+            let comments = c_comments();
+            write!(
+                file,
+                "let TODO = 1; // FIXME: rename
+/* HACK
+   TODOS XXX */ todo();
+println!(\"TODO\");
+"
+            )
+            .unwrap();
+
+            let markers = DEFAULT_MARKERS.map(String::from);
+            let rules = Rules {
+                markers: &markers,
+                ..Rules::from(&comments)
+            };
+            let res = count_lines(file.path(), rules).unwrap();
+            assert_eq!(
+                res.markers,
+                vec![
+                    Marker {
+                        marker: "FIXME".to_string(),
+                        line: 1,
+                        text: "FIXME: rename".to_string()
+                    },
+                    Marker {
+                        marker: "HACK".to_string(),
+                        line: 2,
+                        text: "HACK".to_string()
+                    },
+                    Marker {
+                        marker: "XXX".to_string(),
+                        line: 3,
+                        text: "XXX".to_string()
+                    },
+                ]
+            );
+        }
+    }
+    mod license {
+        use crate::analysis::{count_lines, detect_license};
+        use crate::registry::{Block, CommentType};
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        #[test]
+        fn spdx_tag_wins() {
+            assert_eq!(
+                detect_license(" SPDX-License-Identifier: MIT OR Apache-2.0 \n MIT License\n"),
+                Some("MIT OR Apache-2.0".to_string())
+            );
+        }
+
+        #[test]
+        fn phrase_across_lines() {
+            assert_eq!(
+                detect_license(
+                    " Licensed under the Apache License,\n Version 2.0 (the \"License\")\n"
+                ),
+                Some("Apache-2.0".to_string())
+            );
+            assert_eq!(
+                detect_license(" under the terms of the GNU Lesser General\n Public License\n"),
+                Some("LGPL".to_string())
+            );
+            assert_eq!(detect_license(" just a comment\n"), None);
+        }
+
+        #[test]
+        fn only_leading_comment_block() {
+            let comments = CommentType {
+                line: vec!["//".to_string()],
+                block: Some(Block {
+                    open: "/*".to_string(),
+                    close: "*/".to_string(),
+                }),
+            };
+            let mut header = NamedTempFile::new().unwrap();
+            write!(
+                header,
+                "#!/usr/bin/env run\n\n/*\n * SPDX-License-Identifier: GPL-2.0-only\n */\nint x;\n"
+            )
+            .unwrap();
+            let res = count_lines(header.path(), &comments).unwrap();
+            assert_eq!(res.license, Some("GPL-2.0-only".to_string()));
+
+            let mut late = NamedTempFile::new().unwrap();
+            write!(late, "int x;\n// SPDX-License-Identifier: MIT\n").unwrap();
+            let res = count_lines(late.path(), &comments).unwrap();
+            assert_eq!(res.license, None);
+        }
+    }
+    mod volume {
+        use crate::analysis::{Volume, count_lines, estimate_tokens};
+        use crate::registry::CommentType;
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        #[test]
+        fn tokens() {
+            assert_eq!(estimate_tokens(""), 0);
+            assert_eq!(estimate_tokens("    let x = 1;"), 5);
+            assert_eq!(estimate_tokens("configuration"), 4);
+            assert_eq!(estimate_tokens("a.b()"), 5);
+        }
+
+        #[test]
+        fn split_by_line_kind() {
+            let mut file = NamedTempFile::new().unwrap();
+            let comments = CommentType {
+                line: vec!["#".to_string()],
+                block: None,
+            };
+            write!(file, "# żółw\r\n\nx = 1\n  \ny").unwrap();
+
+            let res = count_lines(file.path(), &comments).unwrap();
+            assert_eq!(
+                res.volume.comment,
+                Volume {
+                    bytes: 11,
+                    chars: 8,
+                    tokens: 2
+                }
+            );
+            assert_eq!(
+                res.volume.blank,
+                Volume {
+                    bytes: 4,
+                    chars: 4,
+                    tokens: 0
+                }
+            );
+            assert_eq!(
+                res.volume.code,
+                Volume {
+                    bytes: 7,
+                    chars: 7,
+                    tokens: 4
+                }
+            );
+            assert_eq!(res.volume.total().bytes, 22);
+        }
+    }
+    mod read_strategy {
+        use crate::analysis::{ReadStrategy, count_lines_with, scan_chunked};
+        use crate::registry::{Block, CommentType};
+        use std::fs::File;
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        #[test]
+        fn small_files_are_buffered() {
+            assert_eq!(ReadStrategy::for_size(10, 1024), ReadStrategy::Buffered);
+            assert_eq!(ReadStrategy::for_size(0, 0), ReadStrategy::Buffered);
+            assert_eq!(ReadStrategy::for_size(1024, 1024), ReadStrategy::Chunked);
+        }
+
+        #[test]
+        fn chunked_and_buffered_counts_match() {
+            let mut file = NamedTempFile::new().unwrap();
+            let comments = CommentType {
+                line: vec!["//".to_string()],
+                block: Some(Block {
+                    open: "/*".to_string(),
+                    close: "*/".to_string(),
+                }),
+            };
+            write!(
+                file,
+                "/* text\r\n text */ code\r\n\r\ncode // text\n// text\ncode /* text"
+            )
+            .unwrap();
+
+            let buffered = count_lines_with(file.path(), &(&comments).into(), u64::MAX).unwrap();
+            let chunked = count_lines_with(file.path(), &(&comments).into(), 0).unwrap();
+            assert_eq!(buffered.code, 3);
+            assert_eq!(chunked, buffered);
+        }
+
+        #[test]
+        fn lines_longer_than_a_chunk() {
+            let mut lines = Vec::new();
+            scan_chunked(&b"abcdefghij\r\nxy\n\nz"[..], 4, |line, len| {
+                lines.push((line.to_string(), len))
+            })
+            .unwrap();
+            assert_eq!(
+                lines,
+                vec![
+                    ("abcdefghij".to_string(), 12),
+                    ("xy".to_string(), 3),
+                    (String::new(), 1),
+                    ("z".to_string(), 1)
+                ]
+            );
+        }
+
+        #[test]
+        fn chunked_file_shrunk_before_scan() {
+            let mut file = NamedTempFile::new().unwrap();
+            write!(file, "line1\nline2\nline3\n").unwrap();
+            let handle = File::open(file.path()).unwrap();
+            file.as_file().set_len(8).unwrap();
+
+            let mut lines = Vec::new();
+            scan_chunked(handle, 4, |line, _| lines.push(line.to_string())).unwrap();
+            assert_eq!(lines, vec!["line1", "li"]);
+        }
+
+        #[test]
+        fn chunked_file_shrunk_during_scan() {
+            const PAGE: usize = 4096;
+            let mut file = NamedTempFile::new().unwrap();
+            // 16 bytes a line, so that the first page ends with a complete line
+            for i in 0..3 * PAGE / 16 {
+                writeln!(file, "line {:010}", i).unwrap();
+            }
+            let handle = File::open(file.path()).unwrap();
+
+            let mut lines = 0;
+            scan_chunked(handle, PAGE, |_, _| {
+                if lines == 0 {
+                    file.as_file().set_len(PAGE as u64 / 2).unwrap();
+                }
+                lines += 1;
+            })
+            .unwrap();
+            assert_eq!(lines, PAGE / 16);
+        }
+    }
     mod is_single_line_comment {
         use crate::analysis::is_single_line_comment;
 
@@ -517,6 +1856,31 @@ mod tests {
         use crate::analysis::is_block_comment;
         use crate::registry::{Block, CommentType};
 
+        #[test]
+        fn non_ascii_code() {
+            let comment_type = CommentType {
+                line: vec!["//".to_string()],
+                block: Some(Block {
+                    open: "/*".to_string(),
+                    close: "*/".to_string(),
+                }),
+            };
+            let mut is_inside_block = false;
+            assert!(!is_block_comment(
+                "let s = \"żółw\"; /* c */",
+                &mut is_inside_block,
+                &comment_type
+            ));
+            assert!(!is_inside_block);
+            // non-ASCII code after the block comment closes
+            assert!(!is_block_comment(
+                "/* c */ let s = \"żółw\";",
+                &mut is_inside_block,
+                &comment_type
+            ));
+            assert!(!is_inside_block);
+        }
+
         #[test]
         fn block_comment_valid_single_line_c_style_no_code() {
             let mut is_inside_block = false;
@@ -0,0 +1,431 @@
+use crate::config_reader::ConfigError;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::{Regex, RegexBuilder};
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// Bytes read from the start of a file when looking for a shebang line.
+const SHEBANG_MAX_LEN: usize = 256;
+/// Bytes read from each end of a file when looking for editor modelines.
+const MODELINE_BYTES: u64 = 1024;
+/// Lines searched at each end of a file, as many as vim searches by default.
+const MODELINE_LINES: usize = 5;
+/// Bytes read from the start of a file when several languages claim its extension.
+const HEURISTIC_BYTES: u64 = 64 * 1024;
+
+/// `vim: ft=perl`, `vi: set filetype=perl:` and the like.
+static VIM_MODELINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:^|\s)(?:vim?|ex):.*?[\s:](?:ft|filetype|syn|syntax)=([\w+#-]+)")
+        .expect("built-in regex is valid")
+});
+/// `-*- mode: tcl -*-` or the short form `-*- tcl -*-`.
+static EMACS_MODELINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"-\*-\s*(.*?)\s*-\*-").expect("built-in regex is valid"));
+/// `mode: tcl` inside a `Local Variables:` block at the end of a file.
+static EMACS_LOCAL_MODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^\W*mode:\s*([\w+#-]+)").expect("built-in regex is valid"));
+
+/// Path globs of files that are always treated as generated.
+pub(crate) const GENERATED_GLOBS: [&str; 7] = [
+    "*.min.js",
+    "*.min.css",
+    "*.pb.go",
+    "*.pb.cc",
+    "*.pb.h",
+    "*_pb2.py",
+    "*.generated.*",
+];
+
+/// Path globs of third-party code that is always treated as vendored.
+pub(crate) const VENDORED_GLOBS: [&str; 5] = [
+    "**/vendor/**",
+    "**/vendored/**",
+    "**/third_party/**",
+    "**/node_modules/**",
+    "**/external/**",
+];
+
+/// Path globs of test code shared by all languages, each language can add its own.
+pub(crate) const TEST_GLOBS: [&str; 9] = [
+    "**/tests/**",
+    "**/test/**",
+    "**/__tests__/**",
+    "**/spec/**",
+    "*_test.*",
+    "test_*.*",
+    "*_spec.*",
+    "*.spec.*",
+    "*.test.*",
+];
+
+/// A set of path globs matched against paths relative to the scanned directory.
+/// As in `.gitignore`, a glob without a `/` is matched against the file name.
+#[derive(Clone)]
+pub(crate) struct PathPatterns {
+    patterns: Vec<String>,
+    set: GlobSet,
+}
+
+impl fmt::Debug for PathPatterns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PathPatterns").field(&self.patterns).finish()
+    }
+}
+
+impl PartialEq for PathPatterns {
+    fn eq(&self, other: &Self) -> bool {
+        self.patterns == other.patterns
+    }
+}
+
+impl PathPatterns {
+    pub(crate) fn new(patterns: Vec<String>) -> Result<Self, ConfigError> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns.iter() {
+            let glob = if pattern.contains('/') {
+                pattern.clone()
+            } else {
+                format!("**/{}", pattern)
+            };
+            builder.add(GlobBuilder::new(&glob).literal_separator(true).build()?);
+        }
+        Ok(Self {
+            patterns,
+            set: builder.build()?,
+        })
+    }
+
+    pub(crate) fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Patterns compiled into the binary, which are known to be valid.
+    pub(crate) fn builtin(patterns: &[&str]) -> Self {
+        PathPatterns::new(patterns.iter().map(ToString::to_string).collect())
+            .expect("built-in globs are valid")
+    }
+
+    pub(crate) fn extend(&mut self, patterns: Vec<String>) -> Result<(), ConfigError> {
+        if patterns.is_empty() {
+            return Ok(());
+        }
+        let mut all = std::mem::take(&mut self.patterns);
+        all.extend(patterns);
+        *self = PathPatterns::new(all)?;
+        Ok(())
+    }
+
+    pub(crate) fn is_match(&self, path: &Path) -> bool {
+        self.set.is_match(path)
+    }
+}
+
+/// Name of the interpreter on the `#!` line of a file, without its directory.
+/// `env` is looked through, together with its options and variable assignments.
+pub(crate) fn shebang_interpreter(path: &Path) -> Option<String> {
+    let mut buf = [0; SHEBANG_MAX_LEN];
+    let mut file = File::open(path).ok()?;
+    let len = file.read(&mut buf).ok()?;
+    let line = buf[..len].split(|&b| b == b'\n').next()?;
+    let line = std::str::from_utf8(line.strip_prefix(b"#!")?).ok()?;
+
+    let mut words = line
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c| c == '"' || c == '\''));
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+        program = program.rsplit('/').next()?;
+    }
+    Some(program.to_string())
+}
+
+/// Names an interpreter answers to, most specific first: `python3.11`, `python3`, `python`.
+pub(crate) fn interpreter_names(interpreter: &str) -> Vec<&str> {
+    let mut names = vec![interpreter];
+    if let Some(idx) = interpreter.find('.') {
+        names.push(&interpreter[..idx]);
+    }
+    names.push(interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.'));
+    names.dedup();
+    names.retain(|name| !name.is_empty());
+    names
+}
+
+/// Content regexes that pick a language among those sharing an extension.
+/// `^` and `$` match at line boundaries.
+#[derive(Debug, Default)]
+pub(crate) struct Heuristics(Vec<Regex>);
+
+impl PartialEq for Heuristics {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(a, b)| a.as_str() == b.as_str())
+    }
+}
+
+impl Heuristics {
+    pub(crate) fn new(patterns: Vec<String>) -> Result<Self, ConfigError> {
+        let regexes = patterns
+            .iter()
+            .map(|pattern| RegexBuilder::new(pattern).multi_line(true).build())
+            .collect::<Result<_, _>>()
+            .map_err(ConfigError::InvalidHeuristic)?;
+        Ok(Self(regexes))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn is_match(&self, content: &str) -> bool {
+        self.0.iter().any(|regex| regex.is_match(content))
+    }
+}
+
+/// The first bytes of a file, enough for content heuristics to decide on.
+pub(crate) fn heuristic_head(path: &Path) -> Option<String> {
+    let mut head = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(HEURISTIC_BYTES)
+        .read_to_end(&mut head)
+        .ok()?;
+    Some(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// Lowercase language name an editor modeline in the first or last lines of a file declares.
+pub(crate) fn modeline_mode(path: &Path) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    let mut head = Vec::new();
+    (&mut file)
+        .take(MODELINE_BYTES)
+        .read_to_end(&mut head)
+        .ok()?;
+    let head = String::from_utf8_lossy(&head);
+    let tail = if len > MODELINE_BYTES {
+        file.seek(SeekFrom::Start(len - MODELINE_BYTES)).ok()?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail).ok()?;
+        String::from_utf8_lossy(&tail).into_owned()
+    } else {
+        head.to_string()
+    };
+
+    let first: Vec<&str> = head.lines().take(MODELINE_LINES).collect();
+    let last: Vec<&str> = tail.lines().rev().take(MODELINE_LINES).collect();
+    // emacs only reads the first line, or the second after a shebang
+    let emacs_line = match first.first() {
+        Some(line) if line.starts_with("#!") => first.get(1),
+        line => line,
+    };
+    let mode = emacs_line
+        .and_then(|line| EMACS_MODELINE.captures(line))
+        .and_then(|caps| emacs_mode(caps.get(1)?.as_str()))
+        .or_else(|| {
+            first
+                .iter()
+                .chain(last.iter())
+                .find_map(|line| Some(VIM_MODELINE.captures(line)?.get(1)?.as_str()))
+        })
+        .or_else(|| {
+            let locals = &tail[tail.find("Local Variables:")?..];
+            Some(EMACS_LOCAL_MODE.captures(locals)?.get(1)?.as_str())
+        })?;
+    Some(mode.to_lowercase())
+}
+
+/// The mode of a `-*-` line, either alone or as a `mode:` variable among others.
+fn emacs_mode(vars: &str) -> Option<&str> {
+    if !vars.contains(':') {
+        return Some(vars.trim()).filter(|mode| !mode.is_empty());
+    }
+    vars.split(';').find_map(|var| {
+        let (name, value) = var.split_once(':')?;
+        (name.trim().eq_ignore_ascii_case("mode")).then(|| value.trim())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    mod path_patterns {
+        use crate::classify::{GENERATED_GLOBS, PathPatterns, TEST_GLOBS, VENDORED_GLOBS};
+        use crate::config_reader::ConfigError;
+        use std::path::Path;
+
+        #[test]
+        fn builtin_generated_globs() {
+            let patterns = PathPatterns::builtin(&GENERATED_GLOBS);
+            assert!(patterns.is_match(Path::new("web/dist/app.min.js")));
+            assert!(patterns.is_match(Path::new("api/service.pb.go")));
+            assert!(!patterns.is_match(Path::new("web/src/app.js")));
+        }
+
+        #[test]
+        fn builtin_vendored_globs() {
+            let patterns = PathPatterns::builtin(&VENDORED_GLOBS);
+            assert!(patterns.is_match(Path::new("vendor/zlib/inflate.c")));
+            assert!(patterns.is_match(Path::new("web/node_modules/react/index.js")));
+            assert!(patterns.is_match(Path::new("libs/third_party/fmt/format.h")));
+            assert!(!patterns.is_match(Path::new("src/vendor.rs")));
+        }
+
+        #[test]
+        fn builtin_test_globs() {
+            let patterns = PathPatterns::builtin(&TEST_GLOBS);
+            assert!(patterns.is_match(Path::new("tests/integration.rs")));
+            assert!(patterns.is_match(Path::new("web/src/__tests__/app.js")));
+            assert!(patterns.is_match(Path::new("server/handler_test.go")));
+            assert!(patterns.is_match(Path::new("pkg/test_parser.py")));
+            assert!(patterns.is_match(Path::new("app.component.spec.ts")));
+            assert!(!patterns.is_match(Path::new("src/attest.rs")));
+            assert!(!patterns.is_match(Path::new("src/test_data/main.c")));
+        }
+
+        #[test]
+        fn file_name_globs() {
+            let patterns = PathPatterns::new(vec!["*.min.js".to_string()]).unwrap();
+            assert!(patterns.is_match(Path::new("app.min.js")));
+            assert!(patterns.is_match(Path::new("web/dist/app.min.js")));
+            assert!(!patterns.is_match(Path::new("web/app.min.js/index.js")));
+        }
+
+        #[test]
+        fn extend_with_user_globs() {
+            let mut patterns = PathPatterns::builtin(&GENERATED_GLOBS);
+            patterns.extend(vec!["src/gen/**".to_string()]).unwrap();
+            assert!(patterns.is_match(Path::new("src/gen/bindings.rs")));
+            assert!(patterns.is_match(Path::new("app.min.js")));
+        }
+
+        #[test]
+        fn invalid_glob() {
+            let err = PathPatterns::new(vec!["src/[gen".to_string()]).unwrap_err();
+            assert!(matches!(err, ConfigError::InvalidGlob(_)));
+        }
+    }
+    mod shebang {
+        use crate::classify::{interpreter_names, shebang_interpreter};
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        fn interpreter(content: &str) -> Option<String> {
+            let mut file = NamedTempFile::new().unwrap();
+            write!(file, "{}", content).unwrap();
+            shebang_interpreter(file.path())
+        }
+
+        #[test]
+        fn interpreters() {
+            assert_eq!(interpreter("#!/bin/bash\necho"), Some("bash".to_string()));
+            assert_eq!(
+                interpreter("#!/usr/bin/env python3\n"),
+                Some("python3".to_string())
+            );
+            assert_eq!(
+                interpreter("#! /usr/bin/env -S LC_ALL=C \"perl -w\"\n"),
+                Some("perl".to_string())
+            );
+            assert_eq!(interpreter("#!/usr/bin/env\n"), None);
+            assert_eq!(interpreter("echo\n#!/bin/sh\n"), None);
+            assert_eq!(interpreter(""), None);
+        }
+
+        #[test]
+        fn version_suffixes() {
+            assert_eq!(
+                interpreter_names("python3.11"),
+                vec!["python3.11", "python3", "python"]
+            );
+            assert_eq!(interpreter_names("python3"), vec!["python3", "python"]);
+            assert_eq!(interpreter_names("bash"), vec!["bash"]);
+        }
+    }
+    mod modeline {
+        use crate::classify::modeline_mode;
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        fn mode(content: &str) -> Option<String> {
+            let mut file = NamedTempFile::new().unwrap();
+            write!(file, "{}", content).unwrap();
+            modeline_mode(file.path())
+        }
+
+        #[test]
+        fn vim() {
+            assert_eq!(mode("# vim: ft=perl\nprint 1;\n"), Some("perl".to_string()));
+            assert_eq!(
+                mode("x\n/* vi: set ts=4 filetype=C: */\n"),
+                Some("c".to_string())
+            );
+            assert_eq!(mode("let vim = 1;\n"), None);
+        }
+
+        #[test]
+        fn emacs() {
+            assert_eq!(
+                mode("# -*- mode: tcl -*-\nputs 1\n"),
+                Some("tcl".to_string())
+            );
+            assert_eq!(
+                mode("#!/bin/sh\n# -*- coding: utf-8; mode: sh -*-\n"),
+                Some("sh".to_string())
+            );
+            assert_eq!(mode("// -*- C++ -*-\n"), Some("c++".to_string()));
+            assert_eq!(mode("x\n# -*- tcl -*-\n"), None);
+        }
+
+        #[test]
+        fn last_lines_of_large_files() {
+            let body = "x = 1\n".repeat(1000);
+            assert_eq!(
+                mode(&format!("{}# vim: ft=python\n", body)),
+                Some("python".to_string())
+            );
+            assert_eq!(
+                mode(&format!(
+                    "{}# Local Variables:\n# mode: ruby\n# End:\n",
+                    body
+                )),
+                Some("ruby".to_string())
+            );
+            assert_eq!(
+                mode(&format!("# vim: ft=perl\n{}{}", body, body)),
+                Some("perl".to_string())
+            );
+            assert_eq!(
+                mode(&format!("{}# vim: ft=perl\n{}", "x\n".repeat(5), body)),
+                None
+            );
+        }
+    }
+
+    mod heuristics {
+        use crate::classify::Heuristics;
+        use crate::config_reader::ConfigError;
+
+        #[test]
+        fn match_any_line() {
+            let objc = Heuristics::new(vec![
+                r"^\s*@interface\b".to_string(),
+                r"^#import\b".to_string(),
+            ])
+            .unwrap();
+            assert!(objc.is_match("// header\n@interface Foo : NSObject\n@end\n"));
+            assert!(!objc.is_match("function y = f(x)\n  y = x; % @interface\nend\n"));
+            assert!(Heuristics::default().is_empty());
+
+            let err = Heuristics::new(vec!["(".to_string()]).unwrap_err();
+            assert!(matches!(err, ConfigError::InvalidHeuristic(_)));
+        }
+    }
+}
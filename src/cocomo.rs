@@ -0,0 +1,182 @@
+use crate::config_reader::{CfgCocomo, ConfigError};
+use serde::Serialize;
+
+/// Yearly salary of one developer, the sloccount default.
+const DEFAULT_SALARY: f64 = 56_286.0;
+/// Multiplier from salary to the full cost of a developer.
+const DEFAULT_OVERHEAD: f64 = 2.4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Model {
+    Basic,
+    /// Basic with its effort scaled by an effort adjustment factor.
+    Intermediate,
+}
+
+/// COCOMO 81 parameters, the organic mode coefficients by default.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Cocomo {
+    model: Model,
+    effort_coefficient: f64,
+    effort_exponent: f64,
+    schedule_coefficient: f64,
+    schedule_exponent: f64,
+    /// Product of the intermediate model cost drivers.
+    eaf: f64,
+    salary: f64,
+    overhead: f64,
+}
+
+impl Default for Cocomo {
+    fn default() -> Self {
+        Self::new(Model::Basic)
+    }
+}
+
+impl Cocomo {
+    fn new(model: Model) -> Self {
+        Self {
+            model,
+            effort_coefficient: match model {
+                Model::Basic => 2.4,
+                Model::Intermediate => 3.2,
+            },
+            effort_exponent: 1.05,
+            schedule_coefficient: 2.5,
+            schedule_exponent: 0.38,
+            eaf: 1.0,
+            salary: DEFAULT_SALARY,
+            overhead: DEFAULT_OVERHEAD,
+        }
+    }
+
+    /// Estimates the cost of writing `loc` lines, already weighted per language.
+    pub(crate) fn estimate(&self, loc: f64) -> Estimate {
+        let kloc = loc / 1000.0;
+        let eaf = match self.model {
+            Model::Basic => 1.0,
+            Model::Intermediate => self.eaf,
+        };
+        let effort = self.effort_coefficient * kloc.powf(self.effort_exponent) * eaf;
+        let schedule = self.schedule_coefficient * effort.powf(self.schedule_exponent);
+        Estimate {
+            model: self.model,
+            kloc,
+            effort,
+            schedule,
+            developers: if schedule > 0.0 {
+                effort / schedule
+            } else {
+                0.0
+            },
+            cost: effort / 12.0 * self.salary * self.overhead,
+        }
+    }
+}
+
+impl TryFrom<CfgCocomo> for Cocomo {
+    type Error = ConfigError;
+
+    fn try_from(cfg: CfgCocomo) -> Result<Self, Self::Error> {
+        let model = match cfg.model.as_deref() {
+            None | Some("basic") => Model::Basic,
+            Some("intermediate") => Model::Intermediate,
+            Some(_) => return Err(ConfigError::InvalidCocomoModel),
+        };
+        let defaults = Cocomo::new(model);
+        let positive = |value: Option<f64>, default: f64| match value {
+            Some(value) if value <= 0.0 || !value.is_finite() => {
+                Err(ConfigError::InvalidCocomoParameter)
+            }
+            Some(value) => Ok(value),
+            None => Ok(default),
+        };
+        Ok(Self {
+            model,
+            effort_coefficient: positive(cfg.effort_coefficient, defaults.effort_coefficient)?,
+            effort_exponent: positive(cfg.effort_exponent, defaults.effort_exponent)?,
+            schedule_coefficient: positive(
+                cfg.schedule_coefficient,
+                defaults.schedule_coefficient,
+            )?,
+            schedule_exponent: positive(cfg.schedule_exponent, defaults.schedule_exponent)?,
+            eaf: positive(cfg.eaf, defaults.eaf)?,
+            salary: positive(cfg.salary, defaults.salary)?,
+            overhead: positive(cfg.overhead, defaults.overhead)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Estimate {
+    pub(crate) model: Model,
+    /// Thousands of weighted lines of code.
+    pub(crate) kloc: f64,
+    /// Person-months.
+    pub(crate) effort: f64,
+    /// Months.
+    pub(crate) schedule: f64,
+    pub(crate) developers: f64,
+    pub(crate) cost: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    mod cocomo {
+        use crate::cocomo::{Cocomo, Model};
+        use crate::config_reader::{CfgCocomo, ConfigError};
+
+        fn cfg(model: Option<&str>) -> CfgCocomo {
+            CfgCocomo {
+                model: model.map(String::from),
+                effort_coefficient: None,
+                effort_exponent: None,
+                schedule_coefficient: None,
+                schedule_exponent: None,
+                eaf: None,
+                salary: None,
+                overhead: None,
+            }
+        }
+
+        #[test]
+        fn basic_organic() {
+            // 10 KLOC: 2.4 * 10^1.05 = 26.93 person-months
+            let estimate = Cocomo::default().estimate(10_000.0);
+            assert_eq!(estimate.model, Model::Basic);
+            assert!((estimate.effort - 26.93).abs() < 0.01);
+            assert!((estimate.schedule - 8.74).abs() < 0.01);
+            assert!((estimate.developers - 3.08).abs() < 0.01);
+            assert!((estimate.cost - 26.93 / 12.0 * 56_286.0 * 2.4).abs() < 100.0);
+
+            let empty = Cocomo::default().estimate(0.0);
+            assert_eq!(empty.effort, 0.0);
+            assert_eq!(empty.developers, 0.0);
+        }
+
+        #[test]
+        fn intermediate_applies_eaf() {
+            let mut cfg = cfg(Some("intermediate"));
+            cfg.eaf = Some(0.5);
+            let cocomo: Cocomo = cfg.try_into().unwrap();
+            let estimate = cocomo.estimate(10_000.0);
+            // 3.2 * 10^1.05 * 0.5
+            assert!((estimate.effort - 17.95).abs() < 0.01);
+        }
+
+        #[test]
+        fn invalid_config() {
+            let res: Result<Cocomo, ConfigError> = cfg(Some("detailed")).try_into();
+            assert!(matches!(res.unwrap_err(), ConfigError::InvalidCocomoModel));
+
+            let mut cfg = cfg(None);
+            cfg.salary = Some(-1.0);
+            let res: Result<Cocomo, ConfigError> = cfg.try_into();
+            assert!(matches!(
+                res.unwrap_err(),
+                ConfigError::InvalidCocomoParameter
+            ));
+        }
+    }
+}
@@ -15,6 +15,23 @@ pub enum ConfigError {
     InvalidLineComment,
     ExtensionMissing,
     InvalidExtension,
+    InvalidGlob(globset::Error),
+    FunctionStartMissing,
+    InvalidFunctionStart(regex::Error),
+    InvalidFunctionScope,
+    InvalidHeuristic(regex::Error),
+    InvalidTabWidth,
+    /// A `decisions` entry is empty, it would match between every two characters.
+    InvalidDecision,
+    /// A `markers` entry is empty, it would be found at every word boundary of a comment.
+    InvalidMarker,
+    InvalidCategory,
+    InvalidCocomoModel,
+    InvalidCocomoParameter,
+    /// `extends` or `patch` names a language that is neither built in nor defined earlier.
+    UnknownLanguage(String),
+    /// Two languages of the configuration claim the same name, file name, interpreter or modeline.
+    LanguageConflict(String),
 }
 
 impl From<std::io::Error> for ConfigError {
@@ -27,36 +44,181 @@ impl From<toml::de::Error> for ConfigError {
         ConfigError::Toml(value)
     }
 }
+impl From<regex::Error> for ConfigError {
+    fn from(value: regex::Error) -> Self {
+        ConfigError::InvalidFunctionStart(value)
+    }
+}
+impl From<globset::Error> for ConfigError {
+    fn from(value: globset::Error) -> Self {
+        ConfigError::InvalidGlob(value)
+    }
+}
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 pub(crate) struct CfgBlock {
     pub(crate) open: Option<String>,
     pub(crate) close: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 pub(crate) struct CfgCommentType {
+    /// Required, empty only for languages with a block comment.
     pub(crate) line: Option<Vec<String>>,
     pub(crate) block: Option<CfgBlock>,
+    /// Set on built-in languages, which may have no comments at all, such as JSON.
+    #[serde(skip)]
+    pub(crate) builtin: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct CfgFunctions {
+    /// Regex matching the line a function starts on, an optional `name` group captures its name.
+    pub(crate) start: Option<String>,
+    /// How the function body is delimited, `braces` (default) or `indent`.
+    pub(crate) scope: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
 pub(crate) struct CfgLangEntry {
     pub(crate) name: Option<String>,
+    /// Language whose comments and rules this one inherits, where it does not set its own.
+    pub(crate) extends: Option<String>,
+    /// Changes the language of the same name instead of defining a new one.
+    pub(crate) patch: Option<bool>,
+    /// One of `programming` (the default), `markup`, `data`, `prose`, `config` and `build`.
+    pub(crate) category: Option<String>,
     pub(crate) extensions: Option<Vec<String>>,
+    /// File names such as `Makefile`, or file name globs such as `Dockerfile.*`.
+    pub(crate) filenames: Option<Vec<String>>,
+    /// Interpreters, such as `python3`, that identify extensionless scripts by their `#!` line.
+    pub(crate) shebangs: Option<Vec<String>>,
+    /// Vim filetypes and Emacs modes, such as `perl`, that a modeline in the file may declare.
+    pub(crate) modelines: Option<Vec<String>>,
+    /// Regexes matched against the content of files whose extension other languages also claim.
+    pub(crate) heuristics: Option<Vec<String>>,
+    /// Shared extensions that fall back to this language when no heuristic matches,
+    /// rather than to the first language claiming them.
+    pub(crate) default_for: Option<Vec<String>>,
     pub(crate) comments: Option<CfgCommentType>,
+    /// Extra path globs of test code written in this language.
+    pub(crate) tests: Option<Vec<String>>,
+    /// Keywords and operators counted as decision points for complexity.
+    pub(crate) decisions: Option<Vec<String>>,
+    pub(crate) functions: Option<CfgFunctions>,
+    /// Columns a tab advances to, also the width of one indentation level.
+    pub(crate) tab_width: Option<u64>,
+    /// Lines wider than this many characters are reported as too long.
+    pub(crate) max_line_width: Option<u64>,
+    /// Words such as `TODO` inventoried when found inside comments, replacing the defaults.
+    pub(crate) markers: Option<Vec<String>>,
+    /// Multiplier of this language's lines in the COCOMO estimate, 1 for programming
+    /// languages and 0 for any other category by default.
+    pub(crate) cocomo_weight: Option<f64>,
+}
+
+impl CfgLangEntry {
+    /// Takes the comments and rules left out from `base`, but nothing that identifies files.
+    pub(crate) fn inherit(self, base: &CfgLangEntry) -> Self {
+        Self {
+            extends: None,
+            category: self.category.or_else(|| base.category.clone()),
+            comments: self.comments.or_else(|| base.comments.clone()),
+            tests: self.tests.or_else(|| base.tests.clone()),
+            decisions: self.decisions.or_else(|| base.decisions.clone()),
+            functions: self.functions.or_else(|| base.functions.clone()),
+            tab_width: self.tab_width.or(base.tab_width),
+            max_line_width: self.max_line_width.or(base.max_line_width),
+            markers: self.markers.or_else(|| base.markers.clone()),
+            cocomo_weight: self.cocomo_weight.or(base.cocomo_weight),
+            ..self
+        }
+    }
+
+    /// `base` changed by this entry: extensions, file names, interpreters, modelines,
+    /// heuristics and test globs are added to, any other field given is replaced.
+    pub(crate) fn patch(self, base: CfgLangEntry) -> Self {
+        fn append(base: Option<Vec<String>>, extra: Option<Vec<String>>) -> Option<Vec<String>> {
+            match (base, extra) {
+                (Some(mut base), Some(extra)) => {
+                    base.extend(extra);
+                    Some(base)
+                }
+                (base, extra) => base.or(extra),
+            }
+        }
+        Self {
+            name: base.name,
+            extends: None,
+            patch: None,
+            category: self.category.or(base.category),
+            extensions: append(base.extensions, self.extensions),
+            filenames: append(base.filenames, self.filenames),
+            shebangs: append(base.shebangs, self.shebangs),
+            modelines: append(base.modelines, self.modelines),
+            heuristics: append(base.heuristics, self.heuristics),
+            default_for: append(base.default_for, self.default_for),
+            comments: self.comments.or(base.comments),
+            tests: append(base.tests, self.tests),
+            decisions: self.decisions.or(base.decisions),
+            functions: self.functions.or(base.functions),
+            tab_width: self.tab_width.or(base.tab_width),
+            max_line_width: self.max_line_width.or(base.max_line_width),
+            markers: self.markers.or(base.markers),
+            cocomo_weight: self.cocomo_weight.or(base.cocomo_weight),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
+pub(crate) struct CfgCocomo {
+    /// `basic` (default) or `intermediate`.
+    pub(crate) model: Option<String>,
+    pub(crate) effort_coefficient: Option<f64>,
+    pub(crate) effort_exponent: Option<f64>,
+    pub(crate) schedule_coefficient: Option<f64>,
+    pub(crate) schedule_exponent: Option<f64>,
+    /// Effort adjustment factor of the intermediate model.
+    pub(crate) eaf: Option<f64>,
+    /// Yearly salary of one developer.
+    pub(crate) salary: Option<f64>,
+    /// Multiplier from salary to the full cost of a developer.
+    pub(crate) overhead: Option<f64>,
+}
+
+/// The languages of `languages.toml`, compiled into the binary.
+#[derive(Debug, Deserialize)]
+pub(crate) struct CfgLanguages {
+    pub(crate) languages: Vec<CfgLangEntry>,
+}
+
+#[derive(Debug, Default, Deserialize)]
 pub struct Config {
     pub dir: PathBuf,
+    /// File the configuration was loaded from, empty for one built in code.
+    #[serde(skip)]
+    pub(crate) path: PathBuf,
+    /// Layered over the built-in languages, unless `replace_builtins` is set.
+    #[serde(default)]
     pub(crate) languages: Vec<CfgLangEntry>,
+    /// Count only the languages of this file, without the built-in ones.
+    pub(crate) replace_builtins: Option<bool>,
+    /// Extra path globs, relative to `dir`, of files that are generated.
+    pub(crate) generated: Option<Vec<String>>,
+    /// Extra path globs, relative to `dir`, of third-party code.
+    pub(crate) vendored: Option<Vec<String>>,
+    /// Count linked and byte-identical files only once.
+    pub(crate) dedup: Option<bool>,
+    /// Detect languages from vim and Emacs modelines, ahead of file names and extensions.
+    pub(crate) sniff_modelines: Option<bool>,
+    pub(crate) cocomo: Option<CfgCocomo>,
 }
 
 impl Config {
     pub fn load(cfg_path: &str) -> Result<Config, ConfigError> {
         let text = std::fs::read_to_string(cfg_path)?;
-        let cfg: Config = toml::from_str(&text)?;
+        let mut cfg: Config = toml::from_str(&text)?;
+        cfg.path = PathBuf::from(cfg_path);
         cfg.validate_dir()?;
         cfg.validate_languages()?;
         Ok(cfg)
@@ -73,7 +235,7 @@ impl Config {
         }
     }
     fn validate_languages(&self) -> Result<(), ConfigError> {
-        if self.languages.is_empty() {
+        if self.replace_builtins == Some(true) && self.languages.is_empty() {
             return Err(ConfigError::LanguagesMissing);
         }
         Ok(())
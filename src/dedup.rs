@@ -0,0 +1,164 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, Metadata};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DuplicateKind {
+    /// Hard links or symbolic links to the same file.
+    Linked,
+    /// Distinct files with the same content.
+    Identical,
+}
+
+/// Paths that would count the same content more than once, sorted so the first one is kept.
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct DuplicateFiles {
+    pub(crate) kind: DuplicateKind,
+    pub(crate) paths: Vec<PathBuf>,
+}
+
+impl DuplicateFiles {
+    /// Every path but the one that is kept.
+    pub(crate) fn copies(&self) -> &[PathBuf] {
+        &self.paths[1..]
+    }
+}
+
+#[cfg(unix)]
+fn file_id(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+fn content_hash(path: &Path) -> Result<blake3::Hash, std::io::Error> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize())
+}
+
+/// Groups `paths` that point to the same file or hold the same bytes.
+/// Only files sharing a size are read, empty and unreadable files are never reported.
+pub(crate) fn find_duplicate_files(paths: &[PathBuf]) -> Vec<DuplicateFiles> {
+    let mut by_id: BTreeMap<(u64, u64), Vec<&PathBuf>> = BTreeMap::new();
+    let mut by_len: BTreeMap<u64, Vec<&PathBuf>> = BTreeMap::new();
+    let mut groups = Vec::new();
+    for path in paths {
+        let Ok(meta) = std::fs::metadata(path) else {
+            continue;
+        };
+        match file_id(&meta) {
+            Some(id) => by_id.entry(id).or_default().push(path),
+            None => by_len.entry(meta.len()).or_default().push(path),
+        }
+    }
+    for linked in by_id.into_values() {
+        let kept = linked
+            .iter()
+            .min()
+            .copied()
+            .expect("groups are never empty");
+        if let Ok(meta) = std::fs::metadata(kept) {
+            by_len.entry(meta.len()).or_default().push(kept);
+        }
+        if linked.len() > 1 {
+            groups.push(group(DuplicateKind::Linked, linked));
+        }
+    }
+    for (len, same_len) in by_len {
+        if len == 0 || same_len.len() < 2 {
+            continue;
+        }
+        let mut by_hash: HashMap<blake3::Hash, Vec<&PathBuf>> = HashMap::new();
+        for path in same_len {
+            if let Ok(hash) = content_hash(path) {
+                by_hash.entry(hash).or_default().push(path);
+            }
+        }
+        groups.extend(
+            by_hash
+                .into_values()
+                .filter(|identical| identical.len() > 1)
+                .map(|identical| group(DuplicateKind::Identical, identical)),
+        );
+    }
+    groups.sort_by(|a, b| a.paths[0].cmp(&b.paths[0]));
+    groups
+}
+
+fn group(kind: DuplicateKind, paths: Vec<&PathBuf>) -> DuplicateFiles {
+    let mut paths: Vec<PathBuf> = paths.into_iter().cloned().collect();
+    paths.sort();
+    DuplicateFiles { kind, paths }
+}
+
+#[cfg(test)]
+mod tests {
+    mod find_duplicate_files {
+        use crate::dedup::{DuplicateKind, find_duplicate_files};
+        use tempfile::tempdir;
+
+        #[test]
+        fn identical_content() {
+            let dir = tempdir().unwrap();
+            let paths: Vec<_> = ["a.h", "b.h", "c.h", "d.h", "e.h"]
+                .iter()
+                .map(|name| dir.path().join(name))
+                .collect();
+            std::fs::write(&paths[0], "int x;\n").unwrap();
+            std::fs::write(&paths[1], "int y;\n").unwrap();
+            std::fs::write(&paths[2], "int x;\n").unwrap();
+            std::fs::write(&paths[3], "").unwrap();
+            std::fs::write(&paths[4], "").unwrap();
+
+            let groups = find_duplicate_files(&paths);
+            assert_eq!(groups.len(), 1);
+            assert_eq!(groups[0].kind, DuplicateKind::Identical);
+            assert_eq!(groups[0].paths, vec![paths[0].clone(), paths[2].clone()]);
+            assert_eq!(groups[0].copies(), &[paths[2].clone()]);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn links() {
+            let dir = tempdir().unwrap();
+            let paths: Vec<_> = ["a.h", "b.h", "c.h", "d.h"]
+                .iter()
+                .map(|name| dir.path().join(name))
+                .collect();
+            std::fs::write(&paths[0], "int x;\n").unwrap();
+            std::fs::hard_link(&paths[0], &paths[1]).unwrap();
+            std::os::unix::fs::symlink(&paths[0], &paths[2]).unwrap();
+            std::fs::write(&paths[3], "int x;\n").unwrap();
+
+            let groups = find_duplicate_files(&paths);
+            assert_eq!(groups.len(), 2);
+            assert_eq!(groups[0].kind, DuplicateKind::Linked);
+            assert_eq!(groups[0].paths, paths[..3]);
+            // only one path of the linked file is compared by content
+            assert_eq!(groups[1].kind, DuplicateKind::Identical);
+            assert_eq!(groups[1].paths, vec![paths[0].clone(), paths[3].clone()]);
+        }
+
+        #[test]
+        fn unreadable_files() {
+            let dir = tempdir().unwrap();
+            let paths: Vec<_> = ["a.h", "gone.h", "b.h"]
+                .iter()
+                .map(|name| dir.path().join(name))
+                .collect();
+            std::fs::write(&paths[0], "int x;\n").unwrap();
+            std::fs::write(&paths[2], "int x;\n").unwrap();
+
+            let groups = find_duplicate_files(&paths);
+            assert_eq!(groups.len(), 1);
+            assert_eq!(groups[0].paths, vec![paths[0].clone(), paths[2].clone()]);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+
+/// Hash of a code line with whitespace runs collapsed, so that re-indented copies still match.
+pub(crate) fn hash_code_line(code: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for word in code.split_whitespace() {
+        word.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// The code lines of one file, as `(line number, hash)` pairs.
+pub(crate) struct Source<'a> {
+    pub(crate) path: &'a Path,
+    pub(crate) language: &'a str,
+    pub(crate) lines: &'a [(u64, u64)],
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct CloneLocation<'a> {
+    pub(crate) path: &'a Path,
+    pub(crate) language: &'a str,
+    /// 1-based first and last line of the copy.
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+}
+
+/// Places holding the same run of code lines.
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct CloneGroup<'a> {
+    /// Length of the run in code lines.
+    pub(crate) lines: usize,
+    pub(crate) locations: Vec<CloneLocation<'a>>,
+}
+
+pub(crate) struct Duplication<'a> {
+    /// Code lines covered by a clone, per source.
+    pub(crate) duplicated: Vec<usize>,
+    /// Clone groups, largest first.
+    pub(crate) groups: Vec<CloneGroup<'a>>,
+}
+
+/// Finds runs of at least `min_lines` code lines repeated within and across sources.
+pub(crate) fn find_clones<'a>(sources: &[Source<'a>], min_lines: usize) -> Duplication<'a> {
+    let min_lines = min_lines.max(1);
+    let windows: Vec<Vec<u64>> = sources
+        .iter()
+        .map(|source| {
+            source
+                .lines
+                .windows(min_lines)
+                .map(|window| {
+                    let mut hasher = DefaultHasher::new();
+                    for (_, hash) in window {
+                        hash.hash(&mut hasher);
+                    }
+                    hasher.finish()
+                })
+                .collect()
+        })
+        .collect();
+
+    // locations are pushed in source then line order, which `follows` relies on
+    let mut groups: HashMap<u64, Vec<(usize, usize)>> = HashMap::new();
+    for (src, hashes) in windows.iter().enumerate() {
+        for (idx, hash) in hashes.iter().enumerate() {
+            groups.entry(*hash).or_default().push((src, idx));
+        }
+    }
+    groups.retain(|_, locations| locations.len() > 1);
+
+    let mut covered: Vec<Vec<bool>> = sources
+        .iter()
+        .map(|source| vec![false; source.lines.len()])
+        .collect();
+    let mut clones = Vec::new();
+    for locations in groups.values() {
+        for &(src, idx) in locations {
+            covered[src][idx..idx + min_lines].fill(true);
+        }
+        // the window before belongs to the same clone, which is reported from its start
+        if follows(&windows, &groups, locations, -1) {
+            continue;
+        }
+        let mut extra = 0;
+        while follows(&windows, &groups, locations, extra + 1) {
+            extra += 1;
+        }
+        let lines = min_lines + extra as usize;
+        clones.push(CloneGroup {
+            lines,
+            locations: locations
+                .iter()
+                .map(|&(src, idx)| CloneLocation {
+                    path: sources[src].path,
+                    language: sources[src].language,
+                    start: sources[src].lines[idx].0,
+                    end: sources[src].lines[idx + lines - 1].0,
+                })
+                .collect(),
+        });
+    }
+    clones.sort_by(|a, b| {
+        b.lines
+            .cmp(&a.lines)
+            .then_with(|| b.locations.len().cmp(&a.locations.len()))
+            .then_with(|| a.locations[0].path.cmp(b.locations[0].path))
+            .then_with(|| a.locations[0].start.cmp(&b.locations[0].start))
+    });
+    Duplication {
+        duplicated: covered
+            .iter()
+            .map(|lines| lines.iter().filter(|&&dup| dup).count())
+            .collect(),
+        groups: clones,
+    }
+}
+
+/// True when every location shifted by `delta` windows forms a group of its own.
+fn follows(
+    windows: &[Vec<u64>],
+    groups: &HashMap<u64, Vec<(usize, usize)>>,
+    locations: &[(usize, usize)],
+    delta: isize,
+) -> bool {
+    let (src, idx) = locations[0];
+    let group = idx
+        .checked_add_signed(delta)
+        .and_then(|idx| windows[src].get(idx))
+        .and_then(|hash| groups.get(hash));
+    group.is_some_and(|group| {
+        group.len() == locations.len()
+            && group
+                .iter()
+                .zip(locations)
+                .all(|(a, b)| a.0 == b.0 && b.1.checked_add_signed(delta) == Some(a.1))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    mod find_clones {
+        use crate::duplicates::{Source, find_clones, hash_code_line};
+        use std::path::Path;
+
+        fn lines(code: &[&str]) -> Vec<(u64, u64)> {
+            code.iter()
+                .enumerate()
+                .map(|(idx, line)| (idx as u64 + 1, hash_code_line(line)))
+                .collect()
+        }
+
+        #[test]
+        fn whitespace_is_normalised() {
+            assert_eq!(
+                hash_code_line("  let x =  1;"),
+                hash_code_line("let x = 1;")
+            );
+            assert_ne!(hash_code_line("let x = 1;"), hash_code_line("let x = 2;"));
+        }
+
+        #[test]
+        fn across_files() {
+            let a = lines(&["a", "b", "c", "d", "e", "x"]);
+            let b = lines(&["y", "a", "b", "c", "d", "e"]);
+            let sources = [
+                Source {
+                    path: Path::new("a.rs"),
+                    language: "Rust",
+                    lines: &a,
+                },
+                Source {
+                    path: Path::new("b.rs"),
+                    language: "Rust",
+                    lines: &b,
+                },
+            ];
+            let res = find_clones(&sources, 3);
+            assert_eq!(res.duplicated, vec![5, 5]);
+            assert_eq!(res.groups.len(), 1);
+            let group = &res.groups[0];
+            assert_eq!(group.lines, 5);
+            assert_eq!(
+                group
+                    .locations
+                    .iter()
+                    .map(|l| (l.path, l.start, l.end))
+                    .collect::<Vec<_>>(),
+                vec![(Path::new("a.rs"), 1, 5), (Path::new("b.rs"), 2, 6)]
+            );
+        }
+
+        #[test]
+        fn shorter_runs_are_ignored() {
+            let a = lines(&["a", "b", "x", "a", "b", "y"]);
+            let sources = [Source {
+                path: Path::new("a.rs"),
+                language: "Rust",
+                lines: &a,
+            }];
+            let res = find_clones(&sources, 3);
+            assert_eq!(res.duplicated, vec![0]);
+            assert!(res.groups.is_empty());
+
+            let res = find_clones(&sources, 2);
+            assert_eq!(res.duplicated, vec![4]);
+            assert_eq!(res.groups.len(), 1);
+        }
+    }
+}
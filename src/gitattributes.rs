@@ -0,0 +1,254 @@
+use globset::{GlobBuilder, GlobMatcher};
+use std::path::{Path, PathBuf};
+
+const FILE_NAME: &str = ".gitattributes";
+
+/// Linguist attributes of one file, `None` where no `.gitattributes` line says anything.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Linguist {
+    /// Value of `linguist-language`, a language name or its lowercase, dash-separated alias.
+    pub(crate) language: Option<String>,
+    pub(crate) vendored: Option<bool>,
+    pub(crate) generated: Option<bool>,
+    pub(crate) documentation: Option<bool>,
+}
+
+impl Linguist {
+    /// `value` is `None` for `!attr`, which returns the attribute to unspecified.
+    fn apply(&mut self, attr: &str, value: Option<&str>) {
+        let flag = value.map(|value| value != "false");
+        match attr {
+            "linguist-language" => self.language = value.map(String::from),
+            "linguist-vendored" => self.vendored = flag,
+            "linguist-generated" => self.generated = flag,
+            "linguist-documentation" => self.documentation = flag,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    /// Patterns without a slash match the file name at any depth below the directory.
+    basename: bool,
+    glob: GlobMatcher,
+    attrs: Vec<(String, Option<String>)>,
+}
+
+impl Rule {
+    /// `below` is relative to the directory of the `.gitattributes` file.
+    fn is_match(&self, below: &Path) -> bool {
+        if self.basename {
+            below
+                .file_name()
+                .is_some_and(|name| self.glob.is_match(name))
+        } else {
+            self.glob.is_match(below)
+        }
+    }
+}
+
+/// The linguist rules of one `.gitattributes` file.
+#[derive(Debug)]
+struct AttributesFile {
+    /// Directory of the file, relative to the walk root.
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+/// Linguist rules of the `.gitattributes` files in the directories enclosing the path
+/// the walk is at, shallower files first. Paths must come in walk order: the rules of
+/// a directory are dropped once the walk leaves it.
+#[derive(Debug, Default)]
+pub(crate) struct GitAttributes {
+    files: Vec<AttributesFile>,
+}
+
+impl GitAttributes {
+    /// Loads `dir/.gitattributes`, if any. `base` is `dir` relative to the walk root.
+    /// An unreadable file is skipped like a missing one.
+    pub(crate) fn load_dir(&mut self, dir: &Path, base: &Path) {
+        if let Ok(text) = std::fs::read_to_string(dir.join(FILE_NAME)) {
+            self.parse(&text, base);
+        }
+    }
+
+    /// Drops the rules of directories that do not enclose `relative`.
+    fn leave(&mut self, relative: &Path) {
+        while self
+            .files
+            .last()
+            .is_some_and(|file| !relative.starts_with(&file.base))
+        {
+            self.files.pop();
+        }
+    }
+
+    /// Lines without linguist attributes, and patterns git would not match a file with, are skipped.
+    fn parse(&mut self, text: &str, base: &Path) {
+        self.leave(base);
+        let mut rules = Vec::new();
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            let Some(pattern) = words.next().filter(|pattern| !pattern.starts_with('#')) else {
+                continue;
+            };
+            let attrs: Vec<(String, Option<String>)> = words
+                .filter_map(|word| {
+                    let (attr, value) = if let Some(attr) = word.strip_prefix('-') {
+                        (attr, Some("false"))
+                    } else if let Some(attr) = word.strip_prefix('!') {
+                        (attr, None)
+                    } else {
+                        match word.split_once('=') {
+                            Some((attr, value)) => (attr, Some(value)),
+                            None => (word, Some("true")),
+                        }
+                    };
+                    attr.starts_with("linguist-")
+                        .then(|| (attr.to_string(), value.map(String::from)))
+                })
+                .collect();
+            if attrs.is_empty() || pattern.ends_with('/') {
+                continue;
+            }
+            let basename = !pattern.contains('/');
+            let Ok(glob) = GlobBuilder::new(pattern.trim_start_matches('/'))
+                .literal_separator(true)
+                .build()
+            else {
+                continue;
+            };
+            rules.push(Rule {
+                basename,
+                glob: glob.compile_matcher(),
+                attrs,
+            });
+        }
+        if !rules.is_empty() {
+            self.files.push(AttributesFile {
+                base: base.to_path_buf(),
+                rules,
+            });
+        }
+    }
+
+    /// Attributes of `relative`, deeper files and later lines overriding earlier ones.
+    pub(crate) fn linguist(&mut self, relative: &Path) -> Linguist {
+        self.leave(relative);
+        let mut linguist = Linguist::default();
+        for file in &self.files {
+            let Ok(below) = relative.strip_prefix(&file.base) else {
+                continue;
+            };
+            for rule in file.rules.iter().filter(|rule| rule.is_match(below)) {
+                for (attr, value) in &rule.attrs {
+                    linguist.apply(attr, value.as_deref());
+                }
+            }
+        }
+        linguist
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod git_attributes {
+        use crate::gitattributes::{GitAttributes, Linguist};
+        use std::path::Path;
+
+        #[test]
+        fn attribute_forms() {
+            let mut attributes = GitAttributes::default();
+            attributes.parse(
+                "# comment\n\
+                 *.inc linguist-language=PHP\n\
+                 third_party/** linguist-vendored\n\
+                 third_party/own/* -linguist-vendored\n\
+                 *.pb.go linguist-generated=true text eol=lf\n\
+                 *.txt text\n",
+                Path::new(""),
+            );
+            assert_eq!(
+                attributes.linguist(Path::new("src/lib/util.inc")),
+                Linguist {
+                    language: Some("PHP".to_string()),
+                    ..Linguist::default()
+                }
+            );
+            assert_eq!(
+                attributes
+                    .linguist(Path::new("third_party/zlib/zlib.c"))
+                    .vendored,
+                Some(true)
+            );
+            assert_eq!(
+                attributes
+                    .linguist(Path::new("third_party/own/own.c"))
+                    .vendored,
+                Some(false)
+            );
+            assert_eq!(
+                attributes.linguist(Path::new("api/api.pb.go")).generated,
+                Some(true)
+            );
+            assert_eq!(
+                attributes.linguist(Path::new("notes.txt")),
+                Linguist::default()
+            );
+            // patterns with a slash are anchored to the directory of the file
+            assert_eq!(
+                attributes
+                    .linguist(Path::new("src/third_party/x.c"))
+                    .vendored,
+                None
+            );
+        }
+
+        #[test]
+        fn nested_files_override() {
+            let mut attributes = GitAttributes::default();
+            attributes.parse("docs/** linguist-documentation\n", Path::new(""));
+            attributes.parse(
+                "api/* !linguist-documentation\n*.md linguist-language=Markdown\n",
+                Path::new("docs"),
+            );
+            assert_eq!(
+                attributes.linguist(Path::new("docs/guide.md")),
+                Linguist {
+                    language: Some("Markdown".to_string()),
+                    documentation: Some(true),
+                    ..Linguist::default()
+                }
+            );
+            assert_eq!(
+                attributes
+                    .linguist(Path::new("docs/api/index.md"))
+                    .documentation,
+                None
+            );
+            assert_eq!(
+                attributes.linguist(Path::new("src/readme.md")),
+                Linguist::default()
+            );
+        }
+
+        #[test]
+        fn rules_of_left_directories_are_dropped() {
+            let mut attributes = GitAttributes::default();
+            attributes.parse("*.c linguist-vendored\n", Path::new("a"));
+            assert_eq!(attributes.linguist(Path::new("a/x.c")).vendored, Some(true));
+            assert_eq!(attributes.linguist(Path::new("b/y.c")).vendored, None);
+            assert!(attributes.files.is_empty());
+        }
+
+        #[test]
+        fn unreadable_file_is_skipped() {
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::create_dir(dir.path().join(".gitattributes")).unwrap();
+            let mut attributes = GitAttributes::default();
+            attributes.load_dir(dir.path(), Path::new(""));
+            assert!(attributes.files.is_empty());
+        }
+    }
+}
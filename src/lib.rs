@@ -1,3 +1,9 @@
 mod analysis;
+mod classify;
+mod cocomo;
 pub mod config_reader;
+mod dedup;
+mod duplicates;
+mod gitattributes;
 pub mod registry;
+pub mod report;
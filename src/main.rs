@@ -1,24 +1,145 @@
-use clap::{CommandFactory, Parser};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use code_cnt::config_reader::{Config, ConfigError};
-use code_cnt::registry::LangRegistry;
-use std::path::PathBuf;
+use code_cnt::registry::{Category, LangRegistry};
+use code_cnt::report::ReportOptions;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the languages in effect after loading the configuration, and where each comes from
+    Languages,
+}
 
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Directory where the files are. Runs with default configuration.
     #[arg(short, long)]
     dir: Option<PathBuf>,
 
     /// Config path
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     cfg: Option<String>,
+
+    /// Print metrics for every production file
+    #[arg(long)]
+    per_file: bool,
+
+    /// List the N most complex production files
+    #[arg(long, value_name = "N")]
+    top_complex: Option<usize>,
+
+    /// List production functions longer than N code lines
+    #[arg(long, value_name = "N")]
+    long_functions: Option<u64>,
+
+    /// List production lines wider than the limit of their language
+    #[arg(long)]
+    long_lines: bool,
+
+    /// List TODO, FIXME and other comment markers
+    #[arg(long)]
+    markers: bool,
+
+    /// Report license totals and list files without a license header
+    #[arg(long)]
+    licenses: bool,
+
+    /// Report production code repeated in runs of at least N code lines
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    duplicates: Option<u64>,
+
+    /// List files reached through several links or copied byte for byte
+    #[arg(long)]
+    duplicate_files: bool,
+
+    /// Count linked and byte-identical files only once
+    #[arg(long)]
+    dedup: bool,
+
+    /// Detect languages from vim and Emacs modelines before file names and extensions
+    #[arg(long)]
+    modelines: bool,
+
+    /// Count only languages of this category, repeat for several (e.g. --category programming)
+    #[arg(long, value_enum, value_name = "CATEGORY")]
+    category: Vec<Category>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text, global = true)]
+    format: Format,
+}
+
+impl Cli {
+    /// Progress messages go to stderr when stdout carries machine-readable output.
+    fn status(&self, msg: &str) {
+        match self.format {
+            Format::Text => println!("{}", msg),
+            Format::Json => eprintln!("{}", msg),
+        }
+    }
+}
+
+fn report(mut reg: LangRegistry, args: &Cli) -> Result<(), ConfigError> {
+    if args.dedup {
+        reg.set_dedup(true);
+    }
+    if args.duplicate_files {
+        reg.set_list_duplicate_files(true);
+    }
+    if args.modelines {
+        reg.set_sniff_modelines(true);
+    }
+    if !args.category.is_empty() {
+        reg.set_categories(args.category.clone());
+    }
+    reg.update_stats()?;
+    let options = ReportOptions {
+        per_file: args.per_file,
+        top_complex: args.top_complex,
+        long_functions: args.long_functions,
+        long_lines: args.long_lines,
+        markers: args.markers,
+        licenses: args.licenses,
+        duplicates: args.duplicates.map(|n| n as usize),
+        duplicate_files: args.duplicate_files,
+    };
+    match args.format {
+        Format::Text => reg.show_report(&options),
+        Format::Json => reg.show_json(&options)?,
+    }
+    Ok(())
+}
+
+/// Built-in languages, or those of `--cfg` layered over them.
+fn languages(args: &Cli) -> Result<(), ConfigError> {
+    let reg = match &args.cfg {
+        Some(cfg) => LangRegistry::with_config(Config::load(cfg)?)?,
+        None => LangRegistry::with_builtins_langs(Path::new(".")),
+    };
+    match args.format {
+        Format::Text => reg.show_languages(),
+        Format::Json => reg.show_languages_json()?,
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), ConfigError> {
     let args = Cli::parse();
-    let arg_dir = args.dir;
-    let arg_cfg = args.cfg;
+    if let Some(Command::Languages) = args.command {
+        return languages(&args);
+    }
+    let arg_dir = args.dir.clone();
+    let arg_cfg = args.cfg.clone();
 
     if arg_cfg.is_none() && arg_dir.is_none() {
         Cli::command().print_long_help()?;
@@ -36,16 +157,12 @@ fn main() -> Result<(), ConfigError> {
                 }
             };
             let config = Config::load(&arg_cfg)?;
-            println!("Config read successfully...");
-            let mut reg = LangRegistry::with_config(config)?;
-            reg.update_stats()?;
-            reg.show_stats();
-            return Ok(());
+            args.status("Config read successfully...");
+            let reg = LangRegistry::with_config(config)?;
+            return report(reg, &args);
         }
     };
-    println!("No external configuration provided. Running with defaults...");
-    let mut reg = LangRegistry::with_builtins_langs(&arg_dir);
-    reg.update_stats()?;
-    reg.show_stats();
-    Ok(())
+    args.status("No external configuration provided. Running with defaults...");
+    let reg = LangRegistry::with_builtins_langs(&arg_dir);
+    report(reg, &args)
 }
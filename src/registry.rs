@@ -1,35 +1,81 @@
-use crate::analysis::count_lines;
-use crate::config_reader::{CfgBlock, CfgCommentType, CfgLangEntry, Config, ConfigError};
-use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
+use crate::analysis::{
+    DEFAULT_MARKERS, DEFAULT_TAB_WIDTH, FileStats, Function, LineLengths, Marker, Rules, Volumes,
+    count_lines,
+};
+use crate::classify::{
+    GENERATED_GLOBS, Heuristics, PathPatterns, TEST_GLOBS, VENDORED_GLOBS, heuristic_head,
+    interpreter_names, modeline_mode, shebang_interpreter,
+};
+use crate::cocomo::{Cocomo, Estimate};
+use crate::config_reader::{
+    CfgBlock, CfgCommentType, CfgFunctions, CfgLangEntry, CfgLanguages, Config, ConfigError,
+};
+use crate::dedup::{DuplicateFiles, find_duplicate_files};
+use crate::duplicates::{Source, find_clones};
+use crate::gitattributes::{GitAttributes, Linguist};
+use crate::report::{
+    BucketReport, CategoryReport, DuplicateFilesReport, Duplicates, FileReport, FunctionReport,
+    LanguageDefinition, LanguageDuplication, LanguageListing, LanguageReport, LicenseTotal,
+    Licenses, LongFunctions, LongLineReport, MarkerReport, Report, ReportOptions, UnlicensedFile,
+};
+use clap::ValueEnum;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::{OsStr, OsString};
 use std::fmt::{self};
 use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
 use walkdir::WalkDir;
 
+/// Number of clone groups listed in the duplication report.
+const CLONE_GROUPS: usize = 20;
+
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 enum LangRegistryError {
-    LangEntryDuplicated { id: LangId, ext: OsString },
+    LangEntryDuplicated { name: String },
+    FilenameDuplicated { language: String, name: OsString },
+    InterpreterDuplicated { language: String, name: String },
+    ModelineDuplicated { language: String, name: String },
+    DefaultDuplicated { language: String, ext: OsString },
 }
 
 impl fmt::Display for LangRegistryError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            LangRegistryError::LangEntryDuplicated { id, ext } => {
+            LangRegistryError::LangEntryDuplicated { name } => {
+                write!(f, "language {} is already defined", name)
+            }
+            LangRegistryError::DefaultDuplicated { language, ext } => {
+                write!(
+                    f,
+                    "extension {} is already the default of {}",
+                    ext.display(),
+                    language
+                )
+            }
+            LangRegistryError::FilenameDuplicated { language, name } => {
                 write!(
                     f,
-                    "Entry already exists (id = {:?}, ext {}), skipping...",
-                    id,
-                    ext.display()
+                    "file name {} is already claimed by {}",
+                    name.display(),
+                    language
                 )
             }
+            LangRegistryError::InterpreterDuplicated { language, name } => {
+                write!(f, "interpreter {} is already claimed by {}", name, language)
+            }
+            LangRegistryError::ModelineDuplicated { language, name } => {
+                write!(f, "modeline {} is already claimed by {}", name, language)
+            }
         }
     }
 }
 #[derive(Clone, Copy, Debug, PartialEq)]
 struct LangId(usize);
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub(crate) struct Block {
     pub(crate) open: String,
     pub(crate) close: String,
@@ -62,7 +108,7 @@ impl TryFrom<CfgCommentType> for CommentType {
 
     fn try_from(comment: CfgCommentType) -> Result<Self, Self::Error> {
         let line = match comment.line {
-            Some(line) if !line.is_empty() => line,
+            Some(line) if !line.is_empty() || comment.block.is_some() || comment.builtin => line,
             _ => return Err(ConfigError::LineCommentMissing),
         };
 
@@ -74,27 +120,400 @@ impl TryFrom<CfgCommentType> for CommentType {
     }
 }
 
+/// Languages known without a configuration file.
+const BUILTIN_LANGUAGES: &str = include_str!("languages.toml");
+/// The test globs every language starts from, compiled once for all of them.
+static DEFAULT_TESTS: LazyLock<PathPatterns> = LazyLock::new(|| PathPatterns::builtin(&TEST_GLOBS));
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Scope {
+    /// The body is enclosed in `{` and `}`.
+    Braces,
+    /// The body is every following line indented deeper than the signature.
+    Indent,
+}
+
+#[derive(Debug)]
+pub(crate) struct FunctionRules {
+    pub(crate) start: Regex,
+    pub(crate) scope: Scope,
+}
+
+impl PartialEq for FunctionRules {
+    fn eq(&self, other: &Self) -> bool {
+        self.start.as_str() == other.start.as_str() && self.scope == other.scope
+    }
+}
+
+impl TryFrom<CfgFunctions> for FunctionRules {
+    type Error = ConfigError;
+
+    fn try_from(cfg_functions: CfgFunctions) -> Result<Self, Self::Error> {
+        let start = match cfg_functions.start {
+            Some(start) if !start.is_empty() => Regex::new(&start)?,
+            _ => return Err(ConfigError::FunctionStartMissing),
+        };
+        let scope = match cfg_functions.scope.as_deref() {
+            None | Some("braces") => Scope::Braces,
+            Some("indent") => Scope::Indent,
+            Some(_) => return Err(ConfigError::InvalidFunctionScope),
+        };
+        Ok(Self { start, scope })
+    }
+}
+
+/// What a language is written for, reports group languages by it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Programming,
+    Markup,
+    Data,
+    Prose,
+    Config,
+    Build,
+}
+
+impl Category {
+    /// The name it is given on the command line and in configuration files.
+    pub(crate) fn name(self) -> String {
+        self.to_possible_value()
+            .expect("no category is skipped")
+            .get_name()
+            .to_string()
+    }
+}
+
+/// Where a language was defined.
+#[derive(Clone, Debug, PartialEq)]
+enum Origin {
+    Builtin,
+    /// The configuration file at this path.
+    Config(PathBuf),
+    /// A built-in language patched by the configuration file at this path.
+    Patched(PathBuf),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Builtin => write!(f, "built-in"),
+            Origin::Config(path) => write!(f, "{}", path.display()),
+            Origin::Patched(path) => write!(f, "built-in, patched in {}", path.display()),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 struct LangSpec {
     name: String,
+    category: Category,
+    origin: Origin,
     extensions: Vec<OsString>,
+    /// Exact file names, such as `Makefile`, matched before extensions.
+    filenames: Vec<OsString>,
+    /// File name globs, such as `Dockerfile.*`, matched after exact names.
+    filename_globs: PathPatterns,
+    /// Interpreters named on the `#!` line of files no name or extension matched.
+    shebangs: Vec<String>,
+    /// Lowercase vim filetypes and Emacs modes declared by a modeline in the file.
+    modelines: Vec<String>,
+    /// Content rules that pick this language among those sharing an extension.
+    heuristics: Heuristics,
+    /// Shared extensions that fall back to this language when no heuristic matches.
+    default_for: Vec<OsString>,
     comments: CommentType,
+    tests: PathPatterns,
+    decisions: Vec<String>,
+    functions: Option<FunctionRules>,
+    tab_width: u64,
+    max_line_width: Option<u64>,
+    markers: Vec<String>,
+    /// Multiplier of this language's lines in the COCOMO estimate, see `LangSpec::cocomo_weight`.
+    cocomo_weight: Option<f64>,
 }
 
 impl LangSpec {
     fn new(name: String, extensions: Vec<OsString>, comments: CommentType) -> Self {
         Self {
             name,
+            category: Category::Programming,
+            origin: Origin::Builtin,
             extensions,
+            filenames: Vec::new(),
+            filename_globs: PathPatterns::builtin(&[]),
+            shebangs: Vec::new(),
+            modelines: Vec::new(),
+            heuristics: Heuristics::default(),
+            default_for: Vec::new(),
             comments,
+            tests: DEFAULT_TESTS.clone(),
+            decisions: Vec::new(),
+            functions: None,
+            tab_width: DEFAULT_TAB_WIDTH,
+            max_line_width: None,
+            markers: DEFAULT_MARKERS.map(String::from).to_vec(),
+            cocomo_weight: None,
+        }
+    }
+
+    /// The configured weight, or by default 1 for programming languages and 0 for the
+    /// others, whose data and markup is not written the way code is.
+    fn cocomo_weight(&self) -> f64 {
+        self.cocomo_weight.unwrap_or(match self.category {
+            Category::Programming => 1.0,
+            _ => 0.0,
+        })
+    }
+
+    fn rules(&self) -> Rules<'_> {
+        Rules {
+            comments: &self.comments,
+            decisions: &self.decisions,
+            functions: self.functions.as_ref(),
+            tab_width: self.tab_width,
+            max_line_width: self.max_line_width,
+            markers: &self.markers,
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Files and lines kept apart from the first-party totals of a language.
+#[derive(Debug, Default, PartialEq)]
+struct Bucket {
+    files: HashMap<PathBuf, FileStats>,
+    loc: u64,
+}
+
+impl Bucket {
+    fn add(&mut self, path: PathBuf, stats: FileStats) {
+        self.loc += stats.code;
+        self.files.insert(path, stats);
+    }
+
+    fn clear(&mut self) {
+        self.files.clear();
+        self.loc = 0;
+    }
+
+    fn report(&self) -> BucketReport {
+        BucketReport {
+            files: self.files.len(),
+            loc: self.loc,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
 struct LangStats {
-    files: HashSet<PathBuf>,
+    files: HashMap<PathBuf, FileStats>,
     loc: u64,
+    generated: Bucket,
+    vendored: Bucket,
+    /// Files marked `linguist-documentation` in `.gitattributes`.
+    documentation: Bucket,
+    test: Bucket,
+}
+
+impl LangStats {
+    /// Test lines per line of production code.
+    fn test_ratio(&self) -> Option<f64> {
+        (self.loc > 0).then(|| self.test.loc as f64 / self.loc as f64)
+    }
+
+    fn complexity(&self) -> u64 {
+        self.files.values().map(|file| file.complexity).sum()
+    }
+
+    fn max_depth(&self) -> u64 {
+        self.files
+            .values()
+            .map(|file| file.max_depth)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn mean_depth(&self) -> f64 {
+        let depth_sum: u64 = self.files.values().map(|file| file.depth_sum).sum();
+        if self.loc == 0 {
+            0.0
+        } else {
+            depth_sum as f64 / self.loc as f64
+        }
+    }
+
+    fn line_lengths(&self) -> LineLengths {
+        let mut lengths = LineLengths::default();
+        for file in self.files.values() {
+            lengths.merge(&file.line_lengths);
+        }
+        lengths
+    }
+
+    fn volume(&self) -> Volumes {
+        let mut volume = Volumes::default();
+        for file in self.files.values() {
+            volume.merge(&file.volume);
+        }
+        volume
+    }
+
+    fn functions(&self) -> impl Iterator<Item = (&Path, &Function)> {
+        self.files.iter().flat_map(|(path, file)| {
+            file.functions
+                .iter()
+                .map(move |function| (path.as_path(), function))
+        })
+    }
+
+    /// Files of every bucket, first-party or not.
+    fn all_files(&self) -> impl Iterator<Item = (&PathBuf, &FileStats)> {
+        self.files
+            .iter()
+            .chain(self.generated.files.iter())
+            .chain(self.vendored.files.iter())
+            .chain(self.documentation.files.iter())
+            .chain(self.test.files.iter())
+    }
+
+    fn markers(&self) -> impl Iterator<Item = (&Path, &Marker)> {
+        self.all_files().flat_map(|(path, file)| {
+            file.markers
+                .iter()
+                .map(move |marker| (path.as_path(), marker))
+        })
+    }
+}
+
+/// Number of occurrences of each comment marker.
+fn marker_counts<'a>(markers: impl Iterator<Item = &'a Marker>) -> BTreeMap<&'a str, usize> {
+    let mut counts = BTreeMap::new();
+    for marker in markers {
+        *counts.entry(marker.marker.as_str()).or_default() += 1;
+    }
+    counts
+}
+
+fn builtin_languages() -> Vec<CfgLangEntry> {
+    let mut builtins: CfgLanguages =
+        toml::from_str(BUILTIN_LANGUAGES).expect("built-in languages are valid TOML");
+    for comments in builtins
+        .languages
+        .iter_mut()
+        .filter_map(|l| l.comments.as_mut())
+    {
+        comments.builtin = true;
+    }
+    builtins.languages
+}
+
+/// Applies `extends` and `patch`, whose target is an earlier entry or else a built-in language.
+/// Each entry comes with where it was defined, `path` being the configuration file.
+fn resolve_languages(
+    languages: Vec<CfgLangEntry>,
+    builtins: &[CfgLangEntry],
+    path: &Path,
+) -> Result<Vec<(CfgLangEntry, Origin)>, ConfigError> {
+    let mut resolved: Vec<(CfgLangEntry, Origin)> = Vec::new();
+    for language in languages {
+        let named = |entry: &&CfgLangEntry, name: &str| entry.name.as_deref() == Some(name);
+        if language.patch == Some(true) {
+            let name = language
+                .name
+                .clone()
+                .ok_or(ConfigError::LanguageNameMissing)?;
+            match resolved.iter().position(|(entry, _)| named(&entry, &name)) {
+                Some(idx) => {
+                    let base = resolved[idx].0.clone();
+                    resolved[idx].0 = language.patch(base);
+                }
+                None => {
+                    let base = builtins
+                        .iter()
+                        .find(|entry| named(entry, &name))
+                        .ok_or(ConfigError::UnknownLanguage(name))?;
+                    let origin = Origin::Patched(path.to_path_buf());
+                    resolved.push((language.patch(base.clone()), origin));
+                }
+            }
+        } else if let Some(name) = language.extends.clone() {
+            let base = resolved
+                .iter()
+                .rev()
+                .map(|(entry, _)| entry)
+                .chain(builtins.iter())
+                .find(|entry| named(entry, &name))
+                .ok_or(ConfigError::UnknownLanguage(name))?;
+            let language = language.inherit(base);
+            resolved.push((language, Origin::Config(path.to_path_buf())));
+        } else {
+            resolved.push((language, Origin::Config(path.to_path_buf())));
+        }
+    }
+    Ok(resolved)
+}
+
+/// Extensions, file names, interpreters and modelines a configuration file writes itself,
+/// leaving out those a patched language keeps from its built-in.
+struct Claims {
+    extensions: HashSet<String>,
+    filenames: HashSet<String>,
+    shebangs: HashSet<String>,
+    /// Lowercase, as the built-in modelines are, matching is not case-sensitive.
+    modelines: HashSet<String>,
+}
+
+impl Claims {
+    fn new(written: &[CfgLangEntry]) -> Self {
+        fn claimed<'a>(lists: impl Iterator<Item = &'a Option<Vec<String>>>) -> HashSet<String> {
+            lists.flatten().flatten().cloned().collect()
+        }
+        Self {
+            extensions: claimed(written.iter().map(|e| &e.extensions)),
+            filenames: claimed(written.iter().map(|e| &e.filenames)),
+            shebangs: claimed(written.iter().map(|e| &e.shebangs)),
+            modelines: claimed(written.iter().map(|e| &e.modelines))
+                .iter()
+                .map(|mode| mode.to_lowercase())
+                .collect(),
+        }
+    }
+}
+
+/// `user` followed by the built-in languages it leaves: a built-in is dropped when `user`
+/// has a language of the same name, and loses what `claims` holds; one left with nothing
+/// to identify files by is dropped too.
+fn layer_languages(
+    user: Vec<CfgLangEntry>,
+    claims: &Claims,
+    builtins: Vec<CfgLangEntry>,
+) -> Vec<CfgLangEntry> {
+    fn release(list: Option<Vec<String>>, claimed: &HashSet<String>) -> Option<Vec<String>> {
+        list.map(|list| {
+            list.into_iter()
+                .filter(|item| !claimed.contains(item))
+                .collect()
+        })
+    }
+    let names: HashSet<&str> = user.iter().filter_map(|e| e.name.as_deref()).collect();
+    let kept: Vec<CfgLangEntry> = builtins
+        .into_iter()
+        .filter(|e| !e.name.as_deref().is_some_and(|name| names.contains(name)))
+        .map(|e| CfgLangEntry {
+            extensions: release(e.extensions, &claims.extensions),
+            default_for: release(e.default_for, &claims.extensions),
+            filenames: release(e.filenames, &claims.filenames),
+            shebangs: release(e.shebangs, &claims.shebangs),
+            modelines: release(e.modelines, &claims.modelines),
+            ..e
+        })
+        .filter(|e| {
+            [&e.extensions, &e.filenames, &e.shebangs]
+                .into_iter()
+                .any(|list| list.as_ref().is_some_and(|list| !list.is_empty()))
+        })
+        .collect();
+    user.into_iter().chain(kept).collect()
 }
 
 #[derive(Debug, PartialEq)]
@@ -103,6 +522,83 @@ pub(crate) struct LangEntry {
     stats: LangStats,
 }
 
+impl LangEntry {
+    fn definition(&self) -> LanguageDefinition<'_> {
+        let spec = &self.spec;
+        LanguageDefinition {
+            name: &spec.name,
+            category: spec.category,
+            extensions: spec
+                .extensions
+                .iter()
+                .map(|ext| ext.to_string_lossy())
+                .collect(),
+            filenames: spec
+                .filenames
+                .iter()
+                .map(|name| name.to_string_lossy())
+                .chain(
+                    spec.filename_globs
+                        .patterns()
+                        .iter()
+                        .map(|glob| glob.into()),
+                )
+                .collect(),
+            line_comments: &spec.comments.line,
+            block_comment: spec.comments.block.as_ref(),
+            source: spec.origin.to_string(),
+        }
+    }
+
+    fn report(&self) -> LanguageReport<'_> {
+        let stats = &self.stats;
+        let lengths: Vec<u64> = stats.functions().map(|(_, f)| f.length).collect();
+        let line_lengths = stats.line_lengths();
+        LanguageReport {
+            name: &self.spec.name,
+            files: stats.files.len(),
+            loc: stats.loc,
+            complexity: stats.complexity(),
+            max_depth: stats.max_depth(),
+            mean_depth: stats.mean_depth(),
+            functions: lengths.len(),
+            mean_function_length: if lengths.is_empty() {
+                0.0
+            } else {
+                lengths.iter().sum::<u64>() as f64 / lengths.len() as f64
+            },
+            max_function_length: lengths.iter().max().copied().unwrap_or(0),
+            max_line_length: line_lengths.max(),
+            mean_line_length: line_lengths.mean(),
+            p95_line_length: line_lengths.percentile(95),
+            vendored: stats.vendored.report(),
+            generated: stats.generated.report(),
+            documentation: stats.documentation.report(),
+            test: stats.test.report(),
+            test_ratio: stats.test_ratio(),
+            markers: marker_counts(stats.markers().map(|(_, marker)| marker)),
+            volume: stats.volume(),
+        }
+    }
+}
+
+fn file_report<'a>(path: &'a Path, language: &'a str, file: &'a FileStats) -> FileReport<'a> {
+    FileReport {
+        path,
+        language,
+        loc: file.code,
+        complexity: file.complexity,
+        functions: file.functions.len(),
+        max_depth: file.max_depth,
+        mean_depth: file.mean_depth(),
+        max_line_length: file.line_lengths.max(),
+        mean_line_length: file.line_lengths.mean(),
+        p95_line_length: file.line_lengths.percentile(95),
+        markers: marker_counts(file.markers.iter()),
+        volume: &file.volume,
+    }
+}
+
 impl TryFrom<CfgLangEntry> for LangEntry {
     type Error = ConfigError;
 
@@ -111,22 +607,87 @@ impl TryFrom<CfgLangEntry> for LangEntry {
             Some(name) => name,
             _ => return Err(ConfigError::LanguageNameMissing),
         };
-        let extensions = match cfg_lang.extensions {
-            Some(extensions) if !extensions.is_empty() => {
-                extensions.into_iter().map(OsString::from).collect()
-            }
-            _ => return Err(ConfigError::ExtensionMissing),
-        };
+        let extensions: Vec<OsString> = cfg_lang
+            .extensions
+            .unwrap_or_default()
+            .into_iter()
+            .map(OsString::from)
+            .collect();
+        let (filename_globs, filenames): (Vec<String>, Vec<String>) = cfg_lang
+            .filenames
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|name| name.contains(['*', '?', '[', '{']));
+        if extensions.is_empty()
+            && filenames.is_empty()
+            && filename_globs.is_empty()
+            && cfg_lang.shebangs.as_ref().is_none_or(Vec::is_empty)
+        {
+            return Err(ConfigError::ExtensionMissing);
+        }
         let comments = match cfg_lang.comments {
             Some(comments) => comments.try_into()?,
             _ => return Err(ConfigError::CommentsMissing),
         };
-        let spec = LangSpec::new(name, extensions, comments);
-        let stats = LangStats {
-            files: HashSet::new(),
-            loc: 0,
+        let mut spec = LangSpec::new(name, extensions, comments);
+        if let Some(category) = cfg_lang.category {
+            spec.category =
+                Category::from_str(&category, false).map_err(|_| ConfigError::InvalidCategory)?;
+        }
+        spec.filenames = filenames.into_iter().map(OsString::from).collect();
+        spec.filename_globs = PathPatterns::new(filename_globs)?;
+        spec.shebangs = cfg_lang.shebangs.unwrap_or_default();
+        spec.modelines = cfg_lang
+            .modelines
+            .unwrap_or_default()
+            .iter()
+            .map(|name| name.to_lowercase())
+            .collect();
+        spec.heuristics = Heuristics::new(cfg_lang.heuristics.unwrap_or_default())?;
+        spec.default_for = cfg_lang
+            .default_for
+            .unwrap_or_default()
+            .into_iter()
+            .map(OsString::from)
+            .collect();
+        if spec
+            .default_for
+            .iter()
+            .any(|ext| !spec.extensions.contains(ext))
+        {
+            return Err(ConfigError::InvalidExtension);
+        }
+        spec.tests.extend(cfg_lang.tests.unwrap_or_default())?;
+        spec.decisions = cfg_lang.decisions.unwrap_or_default();
+        if spec.decisions.iter().any(String::is_empty) {
+            return Err(ConfigError::InvalidDecision);
+        }
+        spec.functions = match cfg_lang.functions {
+            Some(functions) => Some(functions.try_into()?),
+            _ => None,
+        };
+        spec.tab_width = match cfg_lang.tab_width {
+            Some(0) => return Err(ConfigError::InvalidTabWidth),
+            Some(tab_width) => tab_width,
+            _ => DEFAULT_TAB_WIDTH,
+        };
+        spec.max_line_width = cfg_lang.max_line_width;
+        if let Some(markers) = cfg_lang.markers {
+            if markers.iter().any(String::is_empty) {
+                return Err(ConfigError::InvalidMarker);
+            }
+            spec.markers = markers;
+        }
+        spec.cocomo_weight = match cfg_lang.cocomo_weight {
+            Some(weight) if weight < 0.0 || !weight.is_finite() => {
+                return Err(ConfigError::InvalidCocomoParameter);
+            }
+            weight => weight,
         };
-        Ok(Self { spec, stats })
+        Ok(Self {
+            spec,
+            stats: LangStats::default(),
+        })
     }
 }
 
@@ -134,7 +695,23 @@ impl TryFrom<CfgLangEntry> for LangEntry {
 pub struct LangRegistry {
     dir: PathBuf,
     entries: Vec<LangEntry>,
-    map_ext_id: HashMap<OsString, LangId>,
+    /// Languages claiming each extension, the one it falls back to first.
+    map_ext_id: HashMap<OsString, Vec<LangId>>,
+    map_filename_id: HashMap<OsString, LangId>,
+    map_shebang_id: HashMap<String, LangId>,
+    map_modeline_id: HashMap<String, LangId>,
+    /// Read editor modelines, which win over file names and extensions.
+    sniff_modelines: bool,
+    /// Only languages of these categories are counted, those of every category when empty.
+    categories: Vec<Category>,
+    generated: PathPatterns,
+    vendored: PathPatterns,
+    /// Count linked and identical files only once.
+    dedup: bool,
+    /// Look for linked and identical files to list them, even when they are counted.
+    list_duplicate_files: bool,
+    duplicate_files: Vec<DuplicateFiles>,
+    cocomo: Cocomo,
 }
 
 impl Default for LangRegistry {
@@ -145,21 +722,74 @@ impl Default for LangRegistry {
 
 impl LangRegistry {
     fn add_entry(&mut self, spec: LangSpec, stats: LangStats) -> Result<(), LangRegistryError> {
+        if self
+            .entries
+            .iter()
+            .any(|entry| entry.spec.name == spec.name)
+        {
+            return Err(LangRegistryError::LangEntryDuplicated { name: spec.name });
+        }
+        // check every conflict first, so that a rejected entry leaves no id behind
+        if let Some((ext, &id)) = spec.default_for.iter().find_map(|ext| {
+            let first = self.map_ext_id.get(ext)?.first()?;
+            self.get_spec(*first)
+                .default_for
+                .contains(ext)
+                .then_some((ext, first))
+        }) {
+            return Err(LangRegistryError::DefaultDuplicated {
+                language: self.get_spec(id).name.clone(),
+                ext: ext.clone(),
+            });
+        }
+        if let Some((name, &id)) = spec
+            .filenames
+            .iter()
+            .find_map(|name| Some((name, self.map_filename_id.get(name)?)))
+        {
+            return Err(LangRegistryError::FilenameDuplicated {
+                language: self.get_spec(id).name.clone(),
+                name: name.clone(),
+            });
+        }
+        if let Some((name, &id)) = spec
+            .shebangs
+            .iter()
+            .find_map(|name| Some((name, self.map_shebang_id.get(name)?)))
+        {
+            return Err(LangRegistryError::InterpreterDuplicated {
+                language: self.get_spec(id).name.clone(),
+                name: name.clone(),
+            });
+        }
+        if let Some((name, &id)) = spec
+            .modelines
+            .iter()
+            .find_map(|name| Some((name, self.map_modeline_id.get(name)?)))
+        {
+            return Err(LangRegistryError::ModelineDuplicated {
+                language: self.get_spec(id).name.clone(),
+                name: name.clone(),
+            });
+        }
+
+        let id = LangId(self.entries.len());
         for ext in spec.extensions.iter() {
-            let id = LangId(self.entries.len());
-            match self.map_ext_id.entry(ext.clone()) {
-                Entry::Vacant(v) => {
-                    v.insert(id);
-                }
-                Entry::Occupied(e) => {
-                    return Err(LangRegistryError::LangEntryDuplicated {
-                        id: *e.get(),
-                        ext: e.key().clone(),
-                    })
-                }
+            let ids = self.map_ext_id.entry(ext.clone()).or_default();
+            if spec.default_for.contains(ext) {
+                ids.insert(0, id);
+            } else {
+                ids.push(id);
             }
-            self.map_ext_id
-                .insert(ext.clone(), LangId(self.entries.len()));
+        }
+        for name in spec.filenames.iter() {
+            self.map_filename_id.insert(name.clone(), id);
+        }
+        for name in spec.shebangs.iter() {
+            self.map_shebang_id.insert(name.clone(), id);
+        }
+        for name in spec.modelines.iter() {
+            self.map_modeline_id.insert(name.clone(), id);
         }
         self.entries.push(LangEntry { spec, stats });
         Ok(())
@@ -172,8 +802,94 @@ impl LangRegistry {
         &self.entries[id.0].spec
     }
 
-    fn get_entry_id(&self, ext: &OsStr) -> Option<LangId> {
-        self.map_ext_id.get(ext).copied()
+    fn get_entry_ids(&self, ext: &OsStr) -> Option<&[LangId]> {
+        self.map_ext_id.get(ext).map(Vec::as_slice)
+    }
+
+    /// Exact file names win over file name globs, which win over extensions.
+    /// Files none of them match are identified by their shebang line.
+    /// A modeline naming a known language, when sniffed, wins over all of them.
+    fn detect(&self, path: &Path) -> Option<LangId> {
+        if let Some(id) = self.detect_modeline(path) {
+            return Some(id);
+        }
+        if let Some(id) = path
+            .file_name()
+            .and_then(|name| self.map_filename_id.get(name))
+        {
+            return Some(*id);
+        }
+        let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+        if let Some(idx) = self
+            .entries
+            .iter()
+            .position(|entry| entry.spec.filename_globs.is_match(relative))
+        {
+            return Some(LangId(idx));
+        }
+        self.detect_extension(path)
+            .or_else(|| self.detect_shebang(path))
+    }
+
+    /// The longest registered extension wins, so `api.d.ts` is not taken for `ts`
+    /// when `d.ts` is registered. A leading dot does not start an extension.
+    fn detect_extension(&self, path: &Path) -> Option<LangId> {
+        let ids = match path.file_name()?.to_str() {
+            Some(name) => name
+                .char_indices()
+                .filter(|&(idx, c)| c == '.' && idx > 0)
+                .find_map(|(idx, _)| self.get_entry_ids(OsStr::new(&name[idx + 1..]))),
+            None => path.extension().and_then(|ext| self.get_entry_ids(ext)),
+        }?;
+        self.disambiguate(path, ids)
+    }
+
+    /// The first language whose heuristics match the content, otherwise the fallback.
+    fn disambiguate(&self, path: &Path, ids: &[LangId]) -> Option<LangId> {
+        let fallback = ids.first().copied();
+        let mut ruled = ids
+            .iter()
+            .filter(|id| !self.get_spec(**id).heuristics.is_empty())
+            .peekable();
+        if ids.len() < 2 || ruled.peek().is_none() {
+            return fallback;
+        }
+        let Some(content) = heuristic_head(path) else {
+            return fallback;
+        };
+        ruled
+            .find(|id| self.get_spec(**id).heuristics.is_match(&content))
+            .copied()
+            .or(fallback)
+    }
+
+    fn detect_shebang(&self, path: &Path) -> Option<LangId> {
+        if self.map_shebang_id.is_empty() {
+            return None;
+        }
+        let interpreter = shebang_interpreter(path)?;
+        interpreter_names(&interpreter)
+            .into_iter()
+            .find_map(|name| self.map_shebang_id.get(name).copied())
+    }
+
+    /// The language named by `linguist-language`, by name or by its lowercase, dash-separated alias.
+    fn find_language(&self, name: &str) -> Option<LangId> {
+        self.entries
+            .iter()
+            .position(|entry| {
+                entry.spec.name.eq_ignore_ascii_case(name)
+                    || entry.spec.name.replace(' ', "-").eq_ignore_ascii_case(name)
+            })
+            .map(LangId)
+    }
+
+    fn detect_modeline(&self, path: &Path) -> Option<LangId> {
+        if !self.sniff_modelines || self.map_modeline_id.is_empty() {
+            return None;
+        }
+        let mode = modeline_mode(path)?;
+        self.map_modeline_id.get(&mode).copied()
     }
 
     fn clear_locs(&mut self) {
@@ -188,16 +904,300 @@ impl LangRegistry {
         }
     }
 
+    fn clear_buckets(&mut self) {
+        for entry in self.entries.iter_mut() {
+            entry.stats.generated.clear();
+            entry.stats.vendored.clear();
+            entry.stats.documentation.clear();
+            entry.stats.test.clear();
+        }
+    }
+
+    /// Counts a file reached through several links, or copied byte for byte, only once.
+    pub fn set_dedup(&mut self, dedup: bool) {
+        self.dedup = dedup;
+    }
+
+    /// Looks for linked and byte-identical files, to list them with the report.
+    pub fn set_list_duplicate_files(&mut self, list: bool) {
+        self.list_duplicate_files = list;
+    }
+
+    /// Looks for vim and Emacs modelines in the first and last lines of every file.
+    pub fn set_sniff_modelines(&mut self, sniff: bool) {
+        self.sniff_modelines = sniff;
+    }
+
+    /// Counts only languages of `categories`, such as programming languages without YAML or JSON.
+    pub fn set_categories(&mut self, categories: Vec<Category>) {
+        self.categories = categories;
+    }
+
+    fn is_counted(&self, id: LangId) -> bool {
+        self.categories.is_empty() || self.categories.contains(&self.get_spec(id).category)
+    }
+
     pub fn show_stats(&self) {
-        println!("STATS for directory: {}", self.dir.display());
+        self.report(&ReportOptions::default()).print_text();
+    }
+
+    pub fn show_report(&self, options: &ReportOptions) {
+        self.report(options).print_text();
+    }
+
+    pub fn show_json(&self, options: &ReportOptions) -> Result<(), std::io::Error> {
+        self.report(options).print_json()
+    }
+
+    /// Lists every language with its extensions, file names, comments and where it was defined.
+    pub fn show_languages(&self) {
+        self.language_listing().print_text();
+    }
+
+    pub fn show_languages_json(&self) -> Result<(), std::io::Error> {
+        self.language_listing().print_json()
+    }
+
+    fn language_listing(&self) -> LanguageListing<'_> {
+        LanguageListing {
+            languages: self.entries.iter().map(LangEntry::definition).collect(),
+        }
+    }
+
+    fn report(&self, options: &ReportOptions) -> Report<'_> {
+        Report {
+            dir: &self.dir,
+            categories: self.category_reports(),
+            cocomo: self.estimate(),
+            files: options.per_file.then(|| self.file_reports()),
+            most_complex: options.top_complex.map(|count| self.most_complex(count)),
+            long_functions: options.long_functions.map(|threshold| LongFunctions {
+                threshold,
+                functions: self.long_functions(threshold),
+            }),
+            long_lines: options.long_lines.then(|| self.long_lines()),
+            markers: options.markers.then(|| self.markers()),
+            licenses: options.licenses.then(|| self.licenses()),
+            duplicates: options
+                .duplicates
+                .map(|min_lines| self.duplicates(min_lines)),
+            duplicate_files: options.duplicate_files.then_some(DuplicateFilesReport {
+                counted_once: self.dedup,
+                groups: &self.duplicate_files,
+            }),
+        }
+    }
+
+    /// Languages with files in any bucket, grouped by category in the order of `Category`.
+    fn category_reports(&self) -> Vec<CategoryReport<'_>> {
+        let mut categories: BTreeMap<Category, CategoryReport> = BTreeMap::new();
+        for entry in &self.entries {
+            if entry.stats.all_files().next().is_none() {
+                continue;
+            }
+            let category = entry.spec.category;
+            let report = categories.entry(category).or_insert(CategoryReport {
+                category,
+                files: 0,
+                loc: 0,
+                complexity: 0,
+                test: BucketReport { files: 0, loc: 0 },
+                languages: Vec::new(),
+            });
+            let language = entry.report();
+            report.files += language.files;
+            report.loc += language.loc;
+            report.complexity += language.complexity;
+            report.test.files += language.test.files;
+            report.test.loc += language.test.loc;
+            report.languages.push(language);
+        }
+        categories.into_values().collect()
+    }
+
+    /// Production files grouped by language, sorted by path.
+    fn file_reports(&self) -> Vec<FileReport<'_>> {
+        let mut reports = Vec::new();
         for entry in &self.entries {
-            println!(
-                "{}, files: {} loc: {}",
-                entry.spec.name,
-                entry.stats.files.len(),
-                entry.stats.loc
+            let mut files: Vec<(&PathBuf, &FileStats)> = entry.stats.files.iter().collect();
+            files.sort_by(|a, b| a.0.cmp(b.0));
+            reports.extend(
+                files
+                    .into_iter()
+                    .map(|(path, file)| file_report(path, &entry.spec.name, file)),
             );
         }
+        reports
+    }
+
+    /// Production files with the highest complexity, across all languages.
+    fn most_complex(&self, count: usize) -> Vec<FileReport<'_>> {
+        let mut files = self.file_reports();
+        files.sort_by(|a, b| {
+            b.complexity
+                .cmp(&a.complexity)
+                .then_with(|| a.path.cmp(b.path))
+        });
+        files.truncate(count);
+        files
+    }
+
+    /// Production functions longer than `threshold` code lines, longest first.
+    fn long_functions(&self, threshold: u64) -> Vec<FunctionReport<'_>> {
+        let mut functions: Vec<FunctionReport> = self
+            .entries
+            .iter()
+            .flat_map(|entry| {
+                entry
+                    .stats
+                    .functions()
+                    .map(|(path, function)| FunctionReport {
+                        path,
+                        language: &entry.spec.name,
+                        function,
+                    })
+            })
+            .filter(|f| f.function.length > threshold)
+            .collect();
+        functions.sort_by(|a, b| {
+            b.function
+                .length
+                .cmp(&a.function.length)
+                .then_with(|| a.path.cmp(b.path))
+                .then_with(|| a.function.line.cmp(&b.function.line))
+        });
+        functions
+    }
+
+    /// Production lines wider than the limit of their language, by path and line.
+    fn long_lines(&self) -> Vec<LongLineReport<'_>> {
+        let mut lines: Vec<LongLineReport> = self
+            .entries
+            .iter()
+            .filter_map(|entry| entry.spec.max_line_width.map(|limit| (entry, limit)))
+            .flat_map(|(entry, limit)| {
+                entry.stats.files.iter().flat_map(move |(path, file)| {
+                    file.long_lines.iter().map(move |line| LongLineReport {
+                        path,
+                        language: &entry.spec.name,
+                        line,
+                        limit,
+                    })
+                })
+            })
+            .collect();
+        lines.sort_by(|a, b| {
+            a.path
+                .cmp(b.path)
+                .then_with(|| a.line.line.cmp(&b.line.line))
+        });
+        lines
+    }
+
+    /// COCOMO estimate of the first-party code, production and tests, weighted per language.
+    fn estimate(&self) -> Estimate {
+        let loc = self
+            .entries
+            .iter()
+            .map(|entry| {
+                (entry.stats.loc + entry.stats.test.loc) as f64 * entry.spec.cocomo_weight()
+            })
+            .sum();
+        self.cocomo.estimate(loc)
+    }
+
+    /// License totals and unlicensed files over every bucket, third-party code included.
+    fn licenses(&self) -> Licenses<'_> {
+        let mut totals: BTreeMap<&str, LicenseTotal> = BTreeMap::new();
+        let mut unlicensed = Vec::new();
+        for entry in &self.entries {
+            for (path, file) in entry.stats.all_files() {
+                match &file.license {
+                    Some(license) => {
+                        let total = totals.entry(license).or_insert(LicenseTotal {
+                            license,
+                            files: 0,
+                            loc: 0,
+                        });
+                        total.files += 1;
+                        total.loc += file.code;
+                    }
+                    None => unlicensed.push(UnlicensedFile {
+                        path,
+                        language: &entry.spec.name,
+                    }),
+                }
+            }
+        }
+        unlicensed.sort_by(|a, b| a.path.cmp(b.path));
+        Licenses {
+            totals: totals.into_values().collect(),
+            unlicensed,
+        }
+    }
+
+    /// Runs of at least `min_lines` code lines repeated across production files.
+    fn duplicates(&self, min_lines: usize) -> Duplicates<'_> {
+        let mut sources = Vec::new();
+        let mut languages = Vec::new();
+        for entry in &self.entries {
+            if entry.stats.files.is_empty() {
+                continue;
+            }
+            let mut files: Vec<(&PathBuf, &FileStats)> = entry.stats.files.iter().collect();
+            files.sort_by(|a, b| a.0.cmp(b.0));
+            languages.push((entry.spec.name.as_str(), entry.stats.loc, files.len()));
+            sources.extend(files.into_iter().map(|(path, file)| Source {
+                path,
+                language: &entry.spec.name,
+                lines: &file.code_hashes,
+            }));
+        }
+        let mut duplication = find_clones(&sources, min_lines);
+        let mut duplicated = duplication.duplicated.iter();
+        let languages = languages
+            .into_iter()
+            .map(|(name, loc, files)| {
+                let duplicated: usize = duplicated.by_ref().take(files).sum();
+                LanguageDuplication {
+                    name,
+                    loc,
+                    duplicated,
+                    percent: if loc == 0 {
+                        0.0
+                    } else {
+                        duplicated as f64 * 100.0 / loc as f64
+                    },
+                }
+            })
+            .collect();
+        duplication.groups.truncate(CLONE_GROUPS);
+        Duplicates {
+            min_lines,
+            languages,
+            groups: duplication.groups,
+        }
+    }
+
+    /// Comment markers in files of every bucket, by path and line.
+    fn markers(&self) -> Vec<MarkerReport<'_>> {
+        let mut markers: Vec<MarkerReport> = self
+            .entries
+            .iter()
+            .flat_map(|entry| {
+                entry.stats.markers().map(|(path, marker)| MarkerReport {
+                    path,
+                    language: &entry.spec.name,
+                    marker,
+                })
+            })
+            .collect();
+        markers.sort_by(|a, b| {
+            a.path
+                .cmp(b.path)
+                .then_with(|| a.marker.line.cmp(&b.marker.line))
+        });
+        markers
     }
 
     pub fn new() -> Self {
@@ -205,20 +1205,50 @@ impl LangRegistry {
             dir: PathBuf::new(),
             entries: Vec::new(),
             map_ext_id: HashMap::new(),
+            map_filename_id: HashMap::new(),
+            map_shebang_id: HashMap::new(),
+            map_modeline_id: HashMap::new(),
+            sniff_modelines: false,
+            categories: Vec::new(),
+            generated: PathPatterns::builtin(&GENERATED_GLOBS),
+            vendored: PathPatterns::builtin(&VENDORED_GLOBS),
+            dedup: false,
+            list_duplicate_files: false,
+            duplicate_files: Vec::new(),
+            cocomo: Cocomo::default(),
         }
     }
+    /// The languages of `cfg`, taking over from the built-in ones of the same name and
+    /// from any built-in claiming the same files, unless `replace_builtins` is set.
     pub fn with_config(cfg: Config) -> Result<Self, ConfigError> {
         let mut reg = LangRegistry::new();
 
         reg.dir = cfg.dir;
-        for language in cfg.languages {
-            let entry: LangEntry = language.try_into()?;
-            match reg.add_entry(entry.spec, entry.stats) {
-                Ok(_) => { /* do nothing */ }
-                Err(e) => {
-                    println!("{}", e)
-                }
-            };
+        reg.generated.extend(cfg.generated.unwrap_or_default())?;
+        reg.vendored.extend(cfg.vendored.unwrap_or_default())?;
+        reg.dedup = cfg.dedup.unwrap_or(false);
+        reg.sniff_modelines = cfg.sniff_modelines.unwrap_or(false);
+        if let Some(cocomo) = cfg.cocomo {
+            reg.cocomo = cocomo.try_into()?;
+        }
+        let builtins = builtin_languages();
+        // a patch takes over only what it adds, not what its built-in already had
+        let claims = Claims::new(&cfg.languages);
+        let (mut languages, origins): (Vec<_>, Vec<_>) =
+            resolve_languages(cfg.languages, &builtins, &cfg.path)?
+                .into_iter()
+                .unzip();
+        if cfg.replace_builtins != Some(true) {
+            languages = layer_languages(languages, &claims, builtins);
+        }
+        for (idx, language) in languages.into_iter().enumerate() {
+            let mut entry: LangEntry = language.try_into()?;
+            if let Some(origin) = origins.get(idx) {
+                entry.spec.origin = origin.clone();
+            }
+            let name = entry.spec.name.clone();
+            reg.add_entry(entry.spec, entry.stats)
+                .map_err(|e| ConfigError::LanguageConflict(format!("{}: {}", name, e)))?;
         }
         Ok(reg)
     }
@@ -227,69 +1257,92 @@ impl LangRegistry {
         let mut reg = LangRegistry::new();
 
         reg.dir = dir.to_path_buf();
-        match reg.add_entry(
-            LangSpec::new(
-                String::from("Rust"),
-                vec![OsString::from("rs")],
-                CommentType {
-                    line: vec!["//".to_string(), "///".to_string(), "//!".to_string()],
-                    block: Some(Block {
-                        open: "/*".to_string(),
-                        close: "*/".to_string(),
-                    }),
-                },
-            ),
-            LangStats {
-                files: HashSet::new(),
-                loc: 0,
-            },
-        ) {
-            Ok(_) => { /* do nothing */ }
-            Err(e) => {
-                println!("{}", e)
+        for language in builtin_languages() {
+            let entry: LangEntry = language.try_into().expect("built-in languages are valid");
+            reg.add_entry(entry.spec, entry.stats)
+                .expect("built-in languages do not conflict");
+        }
+        reg
+    }
+
+    pub fn update_stats(&mut self) -> std::result::Result<(), std::io::Error> {
+        self.clear_locs();
+        self.clear_paths();
+        self.clear_buckets();
+
+        // a directory comes before its content, so its `.gitattributes` is loaded in time
+        let mut attributes = GitAttributes::default();
+        let mut candidates: Vec<(PathBuf, LangId, Linguist)> = Vec::new();
+        for path in WalkDir::new(&self.dir)
+            .sort_by_file_name()
+            .into_iter()
+            .flatten()
+            .map(|item| item.into_path())
+        {
+            let relative = path.strip_prefix(&self.dir).unwrap_or(&path);
+            if path.is_dir() {
+                attributes.load_dir(&path, relative);
+                continue;
+            }
+            if !path.is_file() {
+                continue;
+            }
+            let linguist = attributes.linguist(relative);
+            let id = linguist
+                .language
+                .as_deref()
+                .and_then(|name| self.find_language(name))
+                .or_else(|| self.detect(&path));
+            if let Some(id) = id.filter(|id| self.is_counted(*id)) {
+                candidates.push((path, id, linguist));
             }
+        }
+        let paths: Vec<PathBuf> = candidates.iter().map(|(path, ..)| path.clone()).collect();
+        // hashing same-size files doubles the reads, so only when the result is used
+        self.duplicate_files = if self.dedup || self.list_duplicate_files {
+            find_duplicate_files(&paths)
+        } else {
+            Vec::new()
         };
-        match reg.add_entry(
-            LangSpec::new(
-                String::from("C"),
-                vec![OsString::from("c"), OsString::from("h")],
-                CommentType {
-                    line: vec!["//".to_string()],
-                    block: Some(Block {
-                        open: "/*".to_string(),
-                        close: "*/".to_string(),
-                    }),
-                },
-            ),
-            LangStats {
-                files: HashSet::new(),
-                loc: 0,
-            },
-        ) {
-            Ok(_) => { /* do nothing */ }
-            Err(e) => {
-                println!("{}", e)
-            }
+        let copies: HashSet<PathBuf> = if self.dedup {
+            self.duplicate_files
+                .iter()
+                .flat_map(|group| group.copies().iter().cloned())
+                .collect()
+        } else {
+            HashSet::new()
         };
-        reg
-    }
 
-    pub fn update_stats(&mut self) -> std::result::Result<(), std::io::Error> {
-        self.clear_locs();
-        self.clear_paths();
-
-        for item in WalkDir::new(&self.dir).into_iter().flatten() {
-            let path = item.into_path();
-            if !path.is_file() {
+        for (path, id, linguist) in candidates {
+            if copies.contains(&path) {
                 continue;
             }
-            if let Some(id) = path.extension().and_then(|ext| self.get_entry_id(ext)) {
-                let comments = &self.get_spec(id).comments;
+            let spec = self.get_spec(id);
 
-                let loc = count_lines(&path, comments)?;
-                let stats = self.stats_mut(id);
-                stats.files.insert(path);
-                stats.loc += loc;
+            let file_stats = count_lines(&path, spec.rules())?;
+            let relative = path.strip_prefix(&self.dir).unwrap_or(&path);
+            // `.gitattributes` wins over the globs, in both directions
+            let vendored = linguist
+                .vendored
+                .unwrap_or_else(|| self.vendored.is_match(relative));
+            let generated = linguist.generated.unwrap_or_else(|| {
+                self.generated.is_match(relative) || file_stats.looks_generated()
+            });
+            let documentation = linguist.documentation.unwrap_or(false);
+            let test = spec.tests.is_match(relative);
+            let stats = self.stats_mut(id);
+            // vendored code keeps its own bucket even when it is also generated
+            if vendored {
+                stats.vendored.add(path, file_stats);
+            } else if generated {
+                stats.generated.add(path, file_stats);
+            } else if documentation {
+                stats.documentation.add(path, file_stats);
+            } else if test {
+                stats.test.add(path, file_stats);
+            } else {
+                stats.loc += file_stats.code;
+                stats.files.insert(path, file_stats);
             }
         }
         Ok(())
@@ -300,10 +1353,14 @@ impl LangRegistry {
 mod tests {
     mod config_to_registry_types_mapping {
         use crate::{
-            config_reader::{CfgBlock, CfgCommentType, CfgLangEntry, ConfigError},
-            registry::{Block, CommentType, LangEntry, LangSpec, LangStats},
+            analysis::{DEFAULT_MARKERS, DEFAULT_TAB_WIDTH},
+            classify::{Heuristics, PathPatterns, TEST_GLOBS},
+            config_reader::{CfgBlock, CfgCommentType, CfgFunctions, CfgLangEntry, ConfigError},
+            registry::{
+                Block, Category, CommentType, FunctionRules, LangEntry, LangSpec, LangStats,
+                Origin, Scope,
+            },
         };
-        use std::collections::HashSet;
         use std::ffi::OsString;
 
         #[test]
@@ -345,6 +1402,35 @@ mod tests {
             );
         }
         #[test]
+        fn try_from_cfg_comment_type_to_comment_type_no_line_comments() {
+            let cfg_comment = CfgCommentType {
+                line: Some(Vec::new()),
+                block: Some(CfgBlock {
+                    open: Some("<!--".to_string()),
+                    close: Some("-->".to_string()),
+                }),
+                ..Default::default()
+            };
+
+            let comment: CommentType = cfg_comment.try_into().unwrap();
+            assert!(comment.line.is_empty());
+        }
+        #[test]
+        fn try_from_cfg_comment_type_to_comment_type_no_comments() {
+            let cfg_comment = |builtin| CfgCommentType {
+                line: Some(Vec::new()),
+                builtin,
+                ..Default::default()
+            };
+
+            let res: Result<CommentType, ConfigError> = cfg_comment(false).try_into();
+            assert!(matches!(res.unwrap_err(), ConfigError::LineCommentMissing));
+            // built-in languages such as JSON have no comments
+            let comment: CommentType = cfg_comment(true).try_into().unwrap();
+            assert_eq!(comment.line, Vec::<String>::new());
+            assert!(comment.block.is_none());
+        }
+        #[test]
         fn try_from_cfg_comment_type_to_comment_type_line_comment_missing() {
             let cfg_comment = CfgCommentType {
                 line: None,
@@ -352,6 +1438,7 @@ mod tests {
                     open: Some("/*".to_string()),
                     close: Some("*/".to_string()),
                 }),
+                ..Default::default()
             };
 
             let res: Result<CommentType, ConfigError> = cfg_comment.try_into();
@@ -363,12 +1450,45 @@ mod tests {
             let cfg_comment = CfgCommentType {
                 line: Some(vec!["//".to_string(), "///".to_string(), "//!".to_string()]),
                 block: None,
+                ..Default::default()
             };
 
             let res: CommentType = cfg_comment.try_into().unwrap();
             assert!(res.block.is_none());
         }
         #[test]
+        fn try_from_cfg_functions_to_function_rules_default_scope() {
+            let cfg_functions = CfgFunctions {
+                start: Some(r"^\s*fn\s+(?P<name>\w+)".to_string()),
+                scope: None,
+            };
+
+            let res: FunctionRules = cfg_functions.try_into().unwrap();
+            assert_eq!(res.scope, Scope::Braces);
+        }
+        #[test]
+        fn try_from_cfg_functions_to_function_rules_invalid_scope() {
+            let cfg_functions = CfgFunctions {
+                start: Some(r"^def\s".to_string()),
+                scope: Some("tabs".to_string()),
+            };
+
+            let res: Result<FunctionRules, ConfigError> = cfg_functions.try_into();
+            let err = res.unwrap_err();
+            assert!(matches!(err, ConfigError::InvalidFunctionScope));
+        }
+        #[test]
+        fn try_from_cfg_functions_to_function_rules_invalid_start() {
+            let cfg_functions = CfgFunctions {
+                start: Some(r"^def\s(".to_string()),
+                scope: Some("indent".to_string()),
+            };
+
+            let res: Result<FunctionRules, ConfigError> = cfg_functions.try_into();
+            let err = res.unwrap_err();
+            assert!(matches!(err, ConfigError::InvalidFunctionStart(_)));
+        }
+        #[test]
         fn try_from_cfg_lang_entry_to_lang_entry_language_name_missing() {
             let cfg_lang_entry = CfgLangEntry {
                 name: None,
@@ -379,7 +1499,9 @@ mod tests {
                         open: Some("/*".to_string()),
                         close: Some("*/".to_string()),
                     }),
+                    ..Default::default()
                 }),
+                ..Default::default()
             };
 
             let res: Result<LangEntry, ConfigError> = cfg_lang_entry.try_into();
@@ -397,7 +1519,9 @@ mod tests {
                         open: Some("/*".to_string()),
                         close: Some("*/".to_string()),
                     }),
+                    ..Default::default()
                 }),
+                ..Default::default()
             };
 
             let res: Result<LangEntry, ConfigError> = cfg_lang_entry.try_into();
@@ -410,6 +1534,7 @@ mod tests {
                 name: Some("Rust".to_string()),
                 extensions: Some(vec![String::from("rs")]),
                 comments: None,
+                ..Default::default()
             };
 
             let res: Result<LangEntry, ConfigError> = cfg_lang_entry.try_into();
@@ -417,6 +1542,58 @@ mod tests {
             assert!(matches!(err, ConfigError::CommentsMissing));
         }
         #[test]
+        fn try_from_cfg_lang_entry_to_lang_entry_empty_decision() {
+            let cfg_lang_entry = CfgLangEntry {
+                name: Some("Rust".to_string()),
+                extensions: Some(vec![String::from("rs")]),
+                comments: Some(CfgCommentType {
+                    line: Some(vec!["//".to_string()]),
+                    ..Default::default()
+                }),
+                decisions: Some(vec!["if".to_string(), String::new()]),
+                ..Default::default()
+            };
+
+            let res: Result<LangEntry, ConfigError> = cfg_lang_entry.try_into();
+            assert!(matches!(res.unwrap_err(), ConfigError::InvalidDecision));
+        }
+        #[test]
+        fn try_from_cfg_lang_entry_to_lang_entry_empty_marker() {
+            let cfg_lang_entry = CfgLangEntry {
+                name: Some("Rust".to_string()),
+                extensions: Some(vec![String::from("rs")]),
+                comments: Some(CfgCommentType {
+                    line: Some(vec!["//".to_string()]),
+                    ..Default::default()
+                }),
+                markers: Some(vec![String::new()]),
+                ..Default::default()
+            };
+
+            let res: Result<LangEntry, ConfigError> = cfg_lang_entry.try_into();
+            assert!(matches!(res.unwrap_err(), ConfigError::InvalidMarker));
+        }
+        #[test]
+        fn try_from_cfg_lang_entry_to_lang_entry_category() {
+            let cfg_lang_entry = |category: Option<&str>| CfgLangEntry {
+                name: Some("YAML".to_string()),
+                extensions: Some(vec![String::from("yml")]),
+                comments: Some(CfgCommentType {
+                    line: Some(vec!["#".to_string()]),
+                    ..Default::default()
+                }),
+                category: category.map(String::from),
+                ..Default::default()
+            };
+
+            let entry: LangEntry = cfg_lang_entry(None).try_into().unwrap();
+            assert_eq!(entry.spec.category, Category::Programming);
+            let entry: LangEntry = cfg_lang_entry(Some("config")).try_into().unwrap();
+            assert_eq!(entry.spec.category, Category::Config);
+            let res: Result<LangEntry, ConfigError> = cfg_lang_entry(Some("Config")).try_into();
+            assert!(matches!(res.unwrap_err(), ConfigError::InvalidCategory));
+        }
+        #[test]
         fn try_from_cfg_lang_entry_to_lang_entry_conversion_ok() {
             let cfg_lang_entry = CfgLangEntry {
                 name: Some("Rust".to_string()),
@@ -427,7 +1604,9 @@ mod tests {
                         open: Some("/*".to_string()),
                         close: Some("*/".to_string()),
                     }),
+                    ..Default::default()
                 }),
+                ..Default::default()
             };
 
             let res: LangEntry = cfg_lang_entry.try_into().unwrap();
@@ -436,36 +1615,54 @@ mod tests {
                 LangEntry {
                     spec: LangSpec {
                         name: "Rust".to_string(),
+                        category: Category::Programming,
+                        origin: Origin::Builtin,
                         extensions: vec![OsString::from("rs")],
+                        filenames: Vec::new(),
+                        filename_globs: PathPatterns::builtin(&[]),
+                        shebangs: Vec::new(),
+                        modelines: Vec::new(),
+                        heuristics: Heuristics::default(),
+                        default_for: Vec::new(),
                         comments: CommentType {
                             line: vec!["//".to_string()],
                             block: Some(Block {
                                 open: "/*".to_string(),
                                 close: "*/".to_string()
                             })
-                        }
+                        },
+                        tests: PathPatterns::builtin(&TEST_GLOBS),
+                        decisions: Vec::new(),
+                        functions: None,
+                        tab_width: DEFAULT_TAB_WIDTH,
+                        max_line_width: None,
+                        markers: DEFAULT_MARKERS.map(String::from).to_vec(),
+                        cocomo_weight: None
                     },
-                    stats: LangStats {
-                        files: HashSet::new(),
-                        loc: 0,
-                    }
+                    stats: LangStats::default()
                 }
             );
         }
     }
     mod lang_registry {
-        use std::collections::HashMap;
-        use std::collections::HashSet;
-        use std::ffi::{OsStr, OsString};
+        use std::collections::{BTreeMap, HashMap};
+        use std::ffi::OsString;
         use std::io::Write;
         use std::path::{Path, PathBuf};
         use tempfile::{tempdir, Builder};
 
+        use crate::analysis::{DEFAULT_MARKERS, DEFAULT_TAB_WIDTH};
+        use crate::classify::{
+            GENERATED_GLOBS, Heuristics, PathPatterns, TEST_GLOBS, VENDORED_GLOBS,
+        };
+        use crate::cocomo::Cocomo;
         use crate::config_reader::{CfgBlock, CfgCommentType, CfgLangEntry, Config, ConfigError};
         use crate::registry::{
-            Block, CommentType, LangEntry, LangId, LangRegistry, LangRegistryError, LangSpec,
-            LangStats,
+            Block, Category, CommentType, FunctionRules, LangEntry, LangId, LangRegistry,
+            LangRegistryError, LangSpec, LangStats, Origin, Scope,
         };
+        use crate::report::ReportOptions;
+        use regex::Regex;
 
         #[test]
         fn add_entry_duplicate_entries() {
@@ -483,35 +1680,54 @@ mod tests {
                             }),
                         },
                     ),
-                    LangStats {
-                        files: HashSet::new(),
-                        loc: 0,
-                    },
+                    LangStats::default(),
                 )
                 .unwrap_err();
 
             assert!(
-                matches!(err, LangRegistryError::LangEntryDuplicated { ext, ..} if ext == OsStr::new("rs"))
+                matches!(err, LangRegistryError::LangEntryDuplicated { name, ..} if name == "Rust")
             );
         }
 
+        #[test]
+        fn add_entry_rejected_leaves_no_ids() {
+            let spec = |name: &str, ext: &str| {
+                let mut spec = LangSpec::new(
+                    name.to_string(),
+                    vec![OsString::from(ext)],
+                    CommentType {
+                        line: vec!["#".to_string()],
+                        block: None,
+                    },
+                );
+                spec.filenames = vec![OsString::from("Makefile")];
+                spec
+            };
+            let mut reg = LangRegistry::new();
+            reg.add_entry(spec("Make", "mk"), LangStats::default())
+                .unwrap();
+            let err = reg
+                .add_entry(spec("BSD Make", "bmk"), LangStats::default())
+                .unwrap_err();
+            assert!(matches!(err, LangRegistryError::FilenameDuplicated { .. }));
+            assert!(!reg.map_ext_id.contains_key(&OsString::from("bmk")));
+            assert_eq!(reg.entries.len(), 1);
+        }
+
         #[test]
         fn add_entry_ok() {
             let mut reg = LangRegistry::with_builtins_langs(Path::new("./dummy_dir/"));
             let size_before_add = reg.entries.len();
             reg.add_entry(
                 LangSpec::new(
-                    String::from("Python"),
-                    vec![OsString::from("py")],
+                    String::from("Pony"),
+                    vec![OsString::from("pony")],
                     CommentType {
-                        line: vec!["#".to_string()],
+                        line: vec!["//".to_string()],
                         block: None,
                     },
                 ),
-                LangStats {
-                    files: HashSet::new(),
-                    loc: 0,
-                },
+                LangStats::default(),
             )
             .unwrap();
             let size_after_add = reg.entries.len();
@@ -525,12 +1741,13 @@ mod tests {
                 dir: "./dummy_dir/".into(),
                 languages: vec![CfgLangEntry {
                     name: Some("Rust".to_string()),
-                    extensions: None,
                     comments: Some(CfgCommentType {
                         line: Some(vec!["//".to_string()]),
-                        block: None,
+                        ..Default::default()
                     }),
+                    ..Default::default()
                 }],
+                ..Default::default()
             };
 
             let err = LangRegistry::with_config(cfg).unwrap_err();
@@ -549,12 +1766,16 @@ mod tests {
                             open: Some("/*".to_string()),
                             close: Some("*/".to_string()),
                         }),
+                        ..Default::default()
                     }),
+                    ..Default::default()
                 }],
+                replace_builtins: Some(true),
+                ..Default::default()
             };
 
             let mut map = HashMap::new();
-            map.insert(OsString::from("rs"), LangId(0));
+            map.insert(OsString::from("rs"), vec![LangId(0)]);
             let registry = LangRegistry::with_config(cfg).unwrap();
             assert_eq!(
                 registry,
@@ -563,68 +1784,96 @@ mod tests {
                     entries: vec![LangEntry {
                         spec: LangSpec {
                             name: "Rust".to_string(),
+                            category: Category::Programming,
+                            origin: Origin::Config(PathBuf::new()),
                             extensions: vec![OsString::from("rs")],
+                            filenames: Vec::new(),
+                            filename_globs: PathPatterns::builtin(&[]),
+                            shebangs: Vec::new(),
+                            modelines: Vec::new(),
+                            heuristics: Heuristics::default(),
+                            default_for: Vec::new(),
                             comments: CommentType {
                                 line: vec!["//".to_string()],
                                 block: Some(Block {
                                     open: "/*".to_string(),
                                     close: "*/".to_string()
                                 })
-                            }
+                            },
+                            tests: PathPatterns::builtin(&TEST_GLOBS),
+                            decisions: Vec::new(),
+                            functions: None,
+                            tab_width: DEFAULT_TAB_WIDTH,
+                            max_line_width: None,
+                            markers: DEFAULT_MARKERS.map(String::from).to_vec(),
+                            cocomo_weight: None
                         },
-                        stats: LangStats {
-                            files: HashSet::new(),
-                            loc: 0,
-                        }
+                        stats: LangStats::default()
                     }],
                     map_ext_id: map,
+                    map_filename_id: HashMap::new(),
+                    map_shebang_id: HashMap::new(),
+                    map_modeline_id: HashMap::new(),
+                    sniff_modelines: false,
+                    categories: Vec::new(),
+                    generated: PathPatterns::builtin(&GENERATED_GLOBS),
+                    vendored: PathPatterns::builtin(&VENDORED_GLOBS),
+                    dedup: false,
+                    list_duplicate_files: false,
+                    duplicate_files: Vec::new(),
+                    cocomo: Cocomo::default(),
                 }
             );
         }
+        const RUST_FUNCTION_START: &str = r#"^\s*(pub(\([^)]*\))?\s+)?(const\s+)?(async\s+)?(unsafe\s+)?(extern\s+"[^"]*"\s+)?fn\s+(?P<name>\w+)"#;
+        const C_FUNCTION_START: &str = r"^(?:[A-Za-z_][\w*]*[\s*]+)+(?P<name>[A-Za-z_]\w*)\s*\(";
+
         #[test]
         fn with_builtins_langs_verify_registry() {
             let reg = LangRegistry::with_builtins_langs(Path::new("./dummy_dir/"));
-            let mut reg_tst = LangRegistry::new();
-            reg_tst.dir = PathBuf::from("./dummy_dir/");
-            reg_tst
-                .add_entry(
-                    LangSpec::new(
-                        String::from("Rust"),
-                        vec![OsString::from("rs")],
-                        CommentType {
-                            line: vec!["//".to_string(), "///".to_string(), "//!".to_string()],
-                            block: Some(Block {
-                                open: "/*".to_string(),
-                                close: "*/".to_string(),
-                            }),
-                        },
-                    ),
-                    LangStats {
-                        files: HashSet::new(),
-                        loc: 0,
-                    },
-                )
-                .unwrap();
-            reg_tst
-                .add_entry(
-                    LangSpec::new(
-                        String::from("C"),
-                        vec![OsString::from("c"), OsString::from("h")],
-                        CommentType {
-                            line: vec!["//".to_string()],
-                            block: Some(Block {
-                                open: "/*".to_string(),
-                                close: "*/".to_string(),
-                            }),
-                        },
-                    ),
-                    LangStats {
-                        files: HashSet::new(),
-                        loc: 0,
-                    },
-                )
-                .unwrap();
-            assert_eq!(reg, reg_tst);
+            assert_eq!(reg.dir, PathBuf::from("./dummy_dir/"));
+            let mut rust = LangSpec::new(
+                String::from("Rust"),
+                vec![OsString::from("rs")],
+                CommentType {
+                    line: vec!["//".to_string(), "///".to_string(), "//!".to_string()],
+                    block: Some(Block {
+                        open: "/*".to_string(),
+                        close: "*/".to_string(),
+                    }),
+                },
+            );
+            rust.decisions = ["if", "for", "while", "loop", "=>", "&&", "||", "?"]
+                .map(String::from)
+                .to_vec();
+            rust.functions = Some(FunctionRules {
+                start: Regex::new(RUST_FUNCTION_START).unwrap(),
+                scope: Scope::Braces,
+            });
+            rust.max_line_width = Some(100);
+            rust.modelines = vec!["rust".to_string()];
+            assert_eq!(reg.entries[0].spec, rust);
+            let mut c = LangSpec::new(
+                String::from("C"),
+                vec![OsString::from("c"), OsString::from("h")],
+                CommentType {
+                    line: vec!["//".to_string()],
+                    block: Some(Block {
+                        open: "/*".to_string(),
+                        close: "*/".to_string(),
+                    }),
+                },
+            );
+            c.decisions = ["if", "for", "while", "case", "&&", "||", "?"]
+                .map(String::from)
+                .to_vec();
+            c.functions = Some(FunctionRules {
+                start: Regex::new(C_FUNCTION_START).unwrap(),
+                scope: Scope::Braces,
+            });
+            c.modelines = vec!["c".to_string()];
+            c.default_for = vec![OsString::from("h")];
+            assert_eq!(reg.entries[1].spec, c);
         }
         #[test]
         fn update_stats_ok() {
@@ -671,5 +1920,1054 @@ mod tests {
             assert_ne!(loc_before_update, loc_after_update);
             assert_eq!(loc_after_update, 5);
         }
+
+        #[test]
+        fn update_stats_generated_bucket() {
+            let dir = tempdir().unwrap();
+            let mut marked = Builder::new().suffix(".c").tempfile_in(dir.path()).unwrap();
+            write!(marked, "/* @generated */\ncode\ncode\n").unwrap();
+            let gen_dir = dir.path().join("gen");
+            std::fs::create_dir(&gen_dir).unwrap();
+            std::fs::write(gen_dir.join("table.c"), "code\n").unwrap();
+            let mut plain = Builder::new().suffix(".c").tempfile_in(dir.path()).unwrap();
+            writeln!(plain, "code").unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.generated.extend(vec!["gen/**".to_string()]).unwrap();
+            reg.update_stats().unwrap();
+
+            let stats = &reg.entries[1].stats;
+            assert_eq!(stats.files.len(), 1);
+            assert_eq!(stats.loc, 1);
+            assert_eq!(stats.generated.files.len(), 2);
+            assert_eq!(stats.generated.loc, 3);
+        }
+
+        #[test]
+        fn update_stats_vendored_bucket() {
+            let dir = tempdir().unwrap();
+            for vendor_dir in ["vendor/zlib", "deps/libfoo"] {
+                let vendor_dir = dir.path().join(vendor_dir);
+                std::fs::create_dir_all(&vendor_dir).unwrap();
+                // generated files under a vendor directory still count as vendored
+                std::fs::write(vendor_dir.join("inflate.c"), "/* @generated */\ncode\n").unwrap();
+            }
+            std::fs::write(dir.path().join("main.c"), "code\ncode\n").unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.vendored.extend(vec!["deps/**".to_string()]).unwrap();
+            reg.update_stats().unwrap();
+
+            let stats = &reg.entries[1].stats;
+            assert_eq!(stats.loc, 2);
+            assert_eq!(stats.vendored.files.len(), 2);
+            assert_eq!(stats.vendored.loc, 2);
+            assert!(stats.generated.files.is_empty());
+        }
+
+        #[test]
+        fn update_stats_test_bucket() {
+            let dir = tempdir().unwrap();
+            std::fs::create_dir(dir.path().join("tests")).unwrap();
+            std::fs::write(dir.path().join("tests/parser.c"), "code\n").unwrap();
+            std::fs::write(dir.path().join("check_parser.c"), "code\n").unwrap();
+            std::fs::write(dir.path().join("parser.c"), "code\ncode\ncode\ncode\n").unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.entries[1]
+                .spec
+                .tests
+                .extend(vec!["check_*.c".to_string()])
+                .unwrap();
+            reg.update_stats().unwrap();
+
+            let stats = &reg.entries[1].stats;
+            assert_eq!(stats.loc, 4);
+            assert_eq!(stats.test.files.len(), 2);
+            assert_eq!(stats.test.loc, 2);
+            assert_eq!(stats.test_ratio(), Some(0.5));
+        }
+
+        #[test]
+        fn with_config_language_test_globs() {
+            let cfg = Config {
+                dir: "./dummy_dir/".into(),
+                languages: vec![CfgLangEntry {
+                    name: Some("Python".to_string()),
+                    extensions: Some(vec!["py".to_string()]),
+                    comments: Some(CfgCommentType {
+                        line: Some(vec!["#".to_string()]),
+                        ..Default::default()
+                    }),
+                    tests: Some(vec!["conftest.py".to_string()]),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+
+            let reg = LangRegistry::with_config(cfg).unwrap();
+            let tests = &reg.entries[0].spec.tests;
+            assert!(tests.is_match(Path::new("pkg/conftest.py")));
+            assert!(tests.is_match(Path::new("pkg/test_io.py")));
+            assert!(!tests.is_match(Path::new("pkg/io.py")));
+        }
+
+        #[test]
+        fn update_stats_complexity() {
+            let dir = tempdir().unwrap();
+            std::fs::write(
+                dir.path().join("simple.c"),
+                "int main() {\n    return 0;\n}\n",
+            )
+            .unwrap();
+            std::fs::write(
+                dir.path().join("branchy.c"),
+                "if (a && b) {\n    // if\n    x = c ? 1 : 2;\n}\n",
+            )
+            .unwrap();
+            std::fs::write(dir.path().join("loop.c"), "while (x) { x--; }\n").unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.update_stats().unwrap();
+
+            let stats = &reg.entries[1].stats;
+            assert_eq!(stats.complexity(), 4);
+            assert_eq!(stats.files[&dir.path().join("branchy.c")].complexity, 3);
+            assert_eq!(stats.files[&dir.path().join("simple.c")].complexity, 0);
+        }
+
+        #[test]
+        fn update_stats_functions() {
+            let dir = tempdir().unwrap();
+            std::fs::write(
+                dir.path().join("lib.rs"),
+                "pub fn a() {\n    b();\n}\n\nfn b() {}\n",
+            )
+            .unwrap();
+            std::fs::write(
+                dir.path().join("main.c"),
+                "#include <stdio.h>\nstatic int add(int a, int b);\n\nint main(void)\n{\n    if (x) {\n        return 1;\n    }\n    return 0;\n}\n",
+            )
+            .unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.update_stats().unwrap();
+
+            let rust: Vec<(&str, u64, u64)> = reg.entries[0]
+                .stats
+                .functions()
+                .map(|(_, f)| (f.name.as_str(), f.line, f.length))
+                .collect();
+            assert_eq!(rust, vec![("a", 1, 3), ("b", 5, 1)]);
+            let c: Vec<(&str, u64, u64)> = reg.entries[1]
+                .stats
+                .functions()
+                .map(|(_, f)| (f.name.as_str(), f.line, f.length))
+                .collect();
+            assert_eq!(c, vec![("main", 4, 7)]);
+        }
+
+        #[test]
+        fn with_config_tab_width() {
+            let language = |tab_width| CfgLangEntry {
+                name: Some("Go".to_string()),
+                extensions: Some(vec!["go".to_string()]),
+                comments: Some(CfgCommentType {
+                    line: Some(vec!["//".to_string()]),
+                    ..Default::default()
+                }),
+                tab_width,
+                ..Default::default()
+            };
+            let cfg = |tab_width| Config {
+                dir: "./dummy_dir/".into(),
+                languages: vec![language(tab_width)],
+                ..Default::default()
+            };
+
+            let reg = LangRegistry::with_config(cfg(Some(8))).unwrap();
+            assert_eq!(reg.entries[0].spec.tab_width, 8);
+            let reg = LangRegistry::with_config(cfg(None)).unwrap();
+            assert_eq!(reg.entries[0].spec.tab_width, DEFAULT_TAB_WIDTH);
+            let err = LangRegistry::with_config(cfg(Some(0))).unwrap_err();
+            assert!(matches!(err, ConfigError::InvalidTabWidth));
+        }
+
+        #[test]
+        fn update_stats_nesting() {
+            let dir = tempdir().unwrap();
+            std::fs::write(dir.path().join("flat.c"), "int x;\nint y;\n").unwrap();
+            std::fs::write(
+                dir.path().join("nested.c"),
+                "void f() {\n\tif (x) {\n\t\t\ty();\n\t}\n}\n",
+            )
+            .unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.update_stats().unwrap();
+
+            let stats = &reg.entries[1].stats;
+            assert_eq!(stats.files[&dir.path().join("nested.c")].max_depth, 3);
+            assert_eq!(stats.max_depth(), 3);
+            assert_eq!(stats.mean_depth(), 5.0 / 7.0);
+        }
+
+        #[test]
+        fn update_stats_long_lines() {
+            let dir = tempdir().unwrap();
+            let long = format!("fn f() {{ {} }}\n", "x;".repeat(60));
+            std::fs::write(dir.path().join("lib.rs"), format!("fn g() {{}}\n{}", long)).unwrap();
+            std::fs::write(dir.path().join("wide.c"), format!("int x;\n{}", long)).unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.update_stats().unwrap();
+
+            // C has no limit, so only the Rust line is reported
+            let lines = reg.long_lines();
+            assert_eq!(lines.len(), 1);
+            assert_eq!(lines[0].path, dir.path().join("lib.rs"));
+            assert_eq!(lines[0].line.line, 2);
+            assert_eq!(lines[0].line.length, 131);
+            assert_eq!(lines[0].limit, 100);
+
+            let report = reg.entries[0].report();
+            assert_eq!(report.max_line_length, 131);
+            assert_eq!(report.p95_line_length, 131);
+        }
+
+        #[test]
+        fn update_stats_markers() {
+            let dir = tempdir().unwrap();
+            std::fs::create_dir(dir.path().join("tests")).unwrap();
+            std::fs::write(
+                dir.path().join("lib.rs"),
+                "// TODO: split\nfn f() {} // TODO later\n/* FIXME */\n",
+            )
+            .unwrap();
+            std::fs::write(dir.path().join("tests/it.rs"), "// TODO: more tests\n").unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.update_stats().unwrap();
+
+            let markers = reg.markers();
+            assert_eq!(markers.len(), 4);
+            assert_eq!(markers[0].marker.text, "TODO: split");
+            assert_eq!(markers[1].marker.line, 2);
+            assert_eq!(markers[2].marker.marker, "FIXME");
+            assert_eq!(markers[3].path, dir.path().join("tests/it.rs"));
+
+            let report = reg.entries[0].report();
+            assert_eq!(report.markers, BTreeMap::from([("FIXME", 1), ("TODO", 3)]));
+        }
+
+        #[test]
+        fn with_config_markers() {
+            let cfg_lang_entry = CfgLangEntry {
+                name: Some("Rust".to_string()),
+                extensions: Some(vec!["rs".to_string()]),
+                comments: Some(CfgCommentType {
+                    line: Some(vec!["//".to_string()]),
+                    ..Default::default()
+                }),
+                markers: Some(vec!["NOTE".to_string()]),
+                ..Default::default()
+            };
+            let entry: LangEntry = cfg_lang_entry.try_into().unwrap();
+            assert_eq!(entry.spec.markers, vec!["NOTE".to_string()]);
+        }
+
+        #[test]
+        fn update_stats_licenses() {
+            let dir = tempdir().unwrap();
+            std::fs::create_dir_all(dir.path().join("vendor/zlib")).unwrap();
+            std::fs::write(
+                dir.path().join("lib.rs"),
+                "// SPDX-License-Identifier: MIT\nfn f() {}\nfn g() {}\n",
+            )
+            .unwrap();
+            std::fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+            std::fs::write(
+                dir.path().join("vendor/zlib/zlib.h"),
+                "/* Permission is hereby granted, free of charge, ... */\nint x;\n",
+            )
+            .unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.update_stats().unwrap();
+
+            // vendored code counts towards the license totals
+            let licenses = reg.licenses();
+            assert_eq!(licenses.totals.len(), 1);
+            assert_eq!(licenses.totals[0].license, "MIT");
+            assert_eq!(licenses.totals[0].files, 2);
+            assert_eq!(licenses.totals[0].loc, 3);
+            assert_eq!(licenses.unlicensed.len(), 1);
+            assert_eq!(licenses.unlicensed[0].path, dir.path().join("main.rs"));
+        }
+
+        #[test]
+        fn update_stats_duplicates() {
+            let dir = tempdir().unwrap();
+            let body = "fn f() {\n    let a = 1;\n    let b = a + 1; // copied\n    b\n}\n";
+            std::fs::write(dir.path().join("a.rs"), format!("fn a() {{}}\n{}", body)).unwrap();
+            std::fs::write(
+                dir.path().join("b.rs"),
+                format!("// a comment\n{}", body.replace("    ", "\t")),
+            )
+            .unwrap();
+            std::fs::write(dir.path().join("lib.c"), "int x;\n").unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.update_stats().unwrap();
+
+            let duplicates = reg.duplicates(4);
+            assert_eq!(duplicates.languages[0].name, "Rust");
+            assert_eq!(duplicates.languages[0].loc, 11);
+            assert_eq!(duplicates.languages[0].duplicated, 10);
+            assert_eq!(duplicates.languages[1].duplicated, 0);
+            assert_eq!(duplicates.groups.len(), 1);
+            let group = &duplicates.groups[0];
+            assert_eq!(group.lines, 5);
+            assert_eq!((group.locations[0].start, group.locations[0].end), (2, 6));
+            assert_eq!(group.locations[1].path, dir.path().join("b.rs"));
+            assert_eq!((group.locations[1].start, group.locations[1].end), (2, 6));
+        }
+
+        #[test]
+        fn update_stats_dedup() {
+            let dir = tempdir().unwrap();
+            std::fs::create_dir_all(dir.path().join("a")).unwrap();
+            std::fs::create_dir_all(dir.path().join("b")).unwrap();
+            std::fs::write(dir.path().join("a/common.h"), "int x;\nint y;\n").unwrap();
+            std::fs::write(dir.path().join("b/common.h"), "int x;\nint y;\n").unwrap();
+            std::fs::write(dir.path().join("b/other.h"), "int z;\n").unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.update_stats().unwrap();
+            // files are only compared when the duplicates are listed or counted once
+            assert_eq!(reg.entries[1].stats.loc, 5);
+            assert!(reg.duplicate_files.is_empty());
+
+            reg.set_list_duplicate_files(true);
+            reg.update_stats().unwrap();
+            assert_eq!(reg.entries[1].stats.loc, 5);
+            assert_eq!(reg.duplicate_files.len(), 1);
+
+            // the copy that sorts first is the one counted
+            reg.set_dedup(true);
+            reg.update_stats().unwrap();
+            assert_eq!(reg.entries[1].stats.loc, 3);
+            assert!(
+                reg.entries[1]
+                    .stats
+                    .files
+                    .contains_key(&dir.path().join("a/common.h"))
+            );
+            assert_eq!(
+                reg.duplicate_files[0].copies(),
+                &[dir.path().join("b/common.h")]
+            );
+        }
+
+        #[test]
+        fn estimate_weights_languages() {
+            let dir = tempdir().unwrap();
+            std::fs::create_dir(dir.path().join("tests")).unwrap();
+            std::fs::write(dir.path().join("lib.rs"), "fn f() {}\n".repeat(600)).unwrap();
+            std::fs::write(dir.path().join("tests/it.rs"), "fn t() {}\n".repeat(400)).unwrap();
+            std::fs::write(dir.path().join("lib.c"), "int x;\n".repeat(500)).unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.entries[1].spec.cocomo_weight = Some(2.0);
+            reg.update_stats().unwrap();
+
+            // tests count, C lines count twice
+            let estimate = reg.estimate();
+            assert_eq!(estimate.kloc, 2.0);
+            assert_eq!(estimate, Cocomo::default().estimate(2000.0));
+        }
+
+        #[test]
+        fn estimate_leaves_out_non_programming_languages() {
+            let dir = tempdir().unwrap();
+            std::fs::write(dir.path().join("lib.rs"), "fn f() {}\n".repeat(1000)).unwrap();
+            std::fs::write(dir.path().join("data.json"), "1\n".repeat(500)).unwrap();
+            std::fs::write(dir.path().join("Cargo.toml"), "a = 1\n".repeat(300)).unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.update_stats().unwrap();
+            assert_eq!(reg.estimate().kloc, 1.0);
+
+            // a weight brings a language back in
+            let toml = reg.find_language("TOML").unwrap();
+            reg.entries[toml.0].spec.cocomo_weight = Some(0.5);
+            assert_eq!(reg.estimate().kloc, 1.15);
+        }
+
+        fn cfg_lang(name: &str, extensions: &[&str], line_comment: &str) -> CfgLangEntry {
+            CfgLangEntry {
+                name: Some(name.to_string()),
+                extensions: Some(extensions.iter().map(ToString::to_string).collect()),
+                comments: Some(CfgCommentType {
+                    line: Some(vec![line_comment.to_string()]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+        }
+
+        fn cfg(dir: &Path, languages: Vec<CfgLangEntry>) -> Config {
+            Config {
+                dir: dir.to_path_buf(),
+                languages,
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn update_stats_filenames() {
+            let dir = tempdir().unwrap();
+            std::fs::create_dir(dir.path().join("docker")).unwrap();
+            std::fs::write(dir.path().join("Makefile"), "all:\n\tcc main.c\n").unwrap();
+            std::fs::write(dir.path().join("rules.mk"), "CC = cc\n").unwrap();
+            std::fs::write(dir.path().join("CMakeLists.txt"), "project(x)\n").unwrap();
+            std::fs::write(dir.path().join("notes.txt"), "hello\n").unwrap();
+            std::fs::write(dir.path().join("docker/Dockerfile.dev"), "FROM x\n").unwrap();
+
+            let make = CfgLangEntry {
+                filenames: Some(vec!["Makefile".to_string()]),
+                ..cfg_lang("Make", &["mk"], "#")
+            };
+            let cmake = CfgLangEntry {
+                extensions: None,
+                filenames: Some(vec!["CMakeLists.txt".to_string()]),
+                ..cfg_lang("CMake", &[], "#")
+            };
+            let docker = CfgLangEntry {
+                extensions: None,
+                filenames: Some(vec!["Dockerfile".to_string(), "Dockerfile.*".to_string()]),
+                ..cfg_lang("Dockerfile", &[], "#")
+            };
+            let text = cfg_lang("Text", &["txt"], "#");
+            let cfg = cfg(dir.path(), vec![make, cmake, docker, text]);
+            let mut reg = LangRegistry::with_config(cfg).unwrap();
+            reg.update_stats().unwrap();
+
+            assert_eq!(reg.entries[0].stats.files.len(), 2);
+            assert_eq!(reg.entries[0].stats.loc, 3);
+            // the exact name wins over the `txt` extension
+            assert_eq!(reg.entries[1].stats.files.len(), 1);
+            assert_eq!(reg.entries[2].stats.files.len(), 1);
+            assert_eq!(
+                reg.entries[3].stats.files.keys().collect::<Vec<_>>(),
+                vec![&dir.path().join("notes.txt")]
+            );
+        }
+
+        #[test]
+        fn with_config_filenames_without_extensions() {
+            let entry: LangEntry = CfgLangEntry {
+                extensions: None,
+                filenames: Some(vec!["Jenkinsfile".to_string(), "*.jenkins".to_string()]),
+                ..cfg_lang("Groovy", &[], "//")
+            }
+            .try_into()
+            .unwrap();
+            assert_eq!(entry.spec.extensions, Vec::<OsString>::new());
+            assert_eq!(entry.spec.filenames, vec![OsString::from("Jenkinsfile")]);
+            assert!(
+                entry
+                    .spec
+                    .filename_globs
+                    .is_match(Path::new("ci/nightly.jenkins"))
+            );
+
+            let res: Result<LangEntry, ConfigError> = CfgLangEntry {
+                extensions: Some(Vec::new()),
+                ..cfg_lang("Nothing", &[], "#")
+            }
+            .try_into();
+            assert!(matches!(res.unwrap_err(), ConfigError::ExtensionMissing));
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn update_stats_shebangs() {
+            let dir = tempdir().unwrap();
+            std::fs::create_dir(dir.path().join("bin")).unwrap();
+            std::fs::write(
+                dir.path().join("bin/deploy"),
+                "#!/usr/bin/env python3.11\n# deploy\nmain()\n",
+            )
+            .unwrap();
+            std::fs::write(dir.path().join("bin/run"), "#!/bin/bash\necho hi\n").unwrap();
+            std::fs::write(dir.path().join("bin/data"), "no shebang\n").unwrap();
+
+            let python = CfgLangEntry {
+                shebangs: Some(vec!["python".to_string()]),
+                ..cfg_lang("Python", &["py"], "#")
+            };
+            let shell = CfgLangEntry {
+                extensions: None,
+                shebangs: Some(vec!["sh".to_string(), "bash".to_string()]),
+                ..cfg_lang("Shell", &[], "#")
+            };
+            let mut reg = LangRegistry::with_config(cfg(dir.path(), vec![python, shell])).unwrap();
+            reg.update_stats().unwrap();
+
+            // the shebang line is a comment in both languages
+            assert_eq!(reg.entries[0].stats.files.len(), 1);
+            assert_eq!(reg.entries[0].stats.loc, 1);
+            assert_eq!(reg.entries[1].stats.files.len(), 1);
+            assert_eq!(reg.entries[1].stats.loc, 1);
+        }
+
+        #[test]
+        fn update_stats_multi_dot_extensions() {
+            let dir = tempdir().unwrap();
+            std::fs::write(dir.path().join("app.ts"), "let a = 1;\n").unwrap();
+            std::fs::write(dir.path().join("app.d.ts"), "declare let a: number;\n").unwrap();
+            std::fs::write(dir.path().join("app.config.ts"), "export {};\n").unwrap();
+            std::fs::write(dir.path().join(".d.ts"), "declare let b: number;\n").unwrap();
+
+            let ts = cfg_lang("TypeScript", &["ts"], "//");
+            let dts = cfg_lang("TypeScript Declarations", &["d.ts"], "//");
+            let mut reg = LangRegistry::with_config(cfg(dir.path(), vec![ts, dts])).unwrap();
+            reg.update_stats().unwrap();
+
+            let mut ts_files: Vec<_> = reg.entries[0].stats.files.keys().collect();
+            ts_files.sort();
+            assert_eq!(
+                ts_files,
+                vec![
+                    &dir.path().join(".d.ts"),
+                    &dir.path().join("app.config.ts"),
+                    &dir.path().join("app.ts")
+                ]
+            );
+            assert_eq!(
+                reg.entries[1].stats.files.keys().collect::<Vec<_>>(),
+                vec![&dir.path().join("app.d.ts")]
+            );
+        }
+
+        #[test]
+        fn update_stats_modelines() {
+            let dir = tempdir().unwrap();
+            std::fs::write(dir.path().join("build.inc"), "# vim: ft=perl\nprint 1;\n").unwrap();
+            std::fs::write(
+                dir.path().join("setup.txt"),
+                "# -*- mode: Perl -*-\nprint 1;\nprint 2;\n",
+            )
+            .unwrap();
+            std::fs::write(
+                dir.path().join("notes.txt"),
+                "# vim: ft=nosuchmode\nhello\n",
+            )
+            .unwrap();
+
+            let perl = CfgLangEntry {
+                modelines: Some(vec!["Perl".to_string()]),
+                ..cfg_lang("Perl", &["pl"], "#")
+            };
+            let text = cfg_lang("Text", &["txt"], "#");
+            let mut reg = LangRegistry::with_config(cfg(dir.path(), vec![perl, text])).unwrap();
+            reg.update_stats().unwrap();
+            // modelines are ignored unless sniffing is on
+            assert_eq!(reg.entries[0].stats.files.len(), 0);
+            assert_eq!(reg.entries[1].stats.files.len(), 2);
+
+            reg.set_sniff_modelines(true);
+            reg.update_stats().unwrap();
+            // the modeline wins over the `txt` extension, unknown modes fall back to it
+            assert_eq!(reg.entries[0].stats.files.len(), 2);
+            assert_eq!(reg.entries[0].stats.loc, 3);
+            assert_eq!(
+                reg.entries[1].stats.files.keys().collect::<Vec<_>>(),
+                vec![&dir.path().join("notes.txt")]
+            );
+        }
+
+        #[test]
+        fn update_stats_shared_extensions() {
+            let dir = tempdir().unwrap();
+            std::fs::write(
+                dir.path().join("view.m"),
+                "#import <UIKit/UIKit.h>\n@interface View\n@end\n",
+            )
+            .unwrap();
+            std::fs::write(
+                dir.path().join("area.m"),
+                "function a = area(r)\n  a = pi * r^2;\nend\n",
+            )
+            .unwrap();
+            std::fs::write(dir.path().join("noop.m"), "% nothing yet\n").unwrap();
+
+            let objc = CfgLangEntry {
+                heuristics: Some(vec![r"^\s*@(interface|implementation)\b".to_string()]),
+                ..cfg_lang("Objective-C", &["m"], "//")
+            };
+            let matlab = CfgLangEntry {
+                heuristics: Some(vec![r"^\s*function\b".to_string()]),
+                default_for: Some(vec!["m".to_string()]),
+                ..cfg_lang("MATLAB", &["m"], "%")
+            };
+            let mut reg = LangRegistry::with_config(cfg(dir.path(), vec![objc, matlab])).unwrap();
+            reg.update_stats().unwrap();
+
+            assert_eq!(
+                reg.entries[0].stats.files.keys().collect::<Vec<_>>(),
+                vec![&dir.path().join("view.m")]
+            );
+            // no heuristic matches `noop.m`, which falls back to the configured default
+            let mut matlab: Vec<_> = reg.entries[1].stats.files.keys().collect();
+            matlab.sort();
+            assert_eq!(
+                matlab,
+                vec![&dir.path().join("area.m"), &dir.path().join("noop.m")]
+            );
+        }
+
+        #[test]
+        fn add_entry_default_for() {
+            let res: Result<LangEntry, ConfigError> = CfgLangEntry {
+                default_for: Some(vec!["pl".to_string()]),
+                ..cfg_lang("Prolog", &["pro"], "%")
+            }
+            .try_into();
+            assert!(matches!(res.unwrap_err(), ConfigError::InvalidExtension));
+
+            let mut reg = LangRegistry::new();
+            for name in ["Perl", "Prolog"] {
+                let entry: LangEntry = CfgLangEntry {
+                    default_for: Some(vec!["pl".to_string()]),
+                    ..cfg_lang(name, &["pl"], "#")
+                }
+                .try_into()
+                .unwrap();
+                let res = reg.add_entry(entry.spec, entry.stats);
+                if name == "Prolog" {
+                    assert!(matches!(
+                        res.unwrap_err(),
+                        LangRegistryError::DefaultDuplicated { language, .. } if language == "Perl"
+                    ));
+                }
+            }
+            assert_eq!(reg.map_ext_id[&OsString::from("pl")], vec![LangId(0)]);
+        }
+
+        #[test]
+        fn update_stats_gitattributes() {
+            let dir = tempdir().unwrap();
+            for sub in ["lib", "docs", "vendor"] {
+                std::fs::create_dir(dir.path().join(sub)).unwrap();
+            }
+            std::fs::write(
+                dir.path().join(".gitattributes"),
+                "*.inc linguist-language=rust\ndocs/* linguist-documentation\n",
+            )
+            .unwrap();
+            std::fs::write(
+                dir.path().join("vendor/.gitattributes"),
+                "*.rs -linguist-vendored\n",
+            )
+            .unwrap();
+            std::fs::write(dir.path().join("lib/macros.inc"), "fn a() {}\n").unwrap();
+            std::fs::write(dir.path().join("docs/example.rs"), "fn b() {}\n").unwrap();
+            std::fs::write(dir.path().join("vendor/patched.rs"), "fn c() {}\n").unwrap();
+            std::fs::write(dir.path().join("vendor/other.c"), "int d;\n").unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.update_stats().unwrap();
+
+            let rust = &reg.entries[0].stats;
+            let mut files: Vec<_> = rust.files.keys().collect();
+            files.sort();
+            assert_eq!(
+                files,
+                vec![
+                    &dir.path().join("lib/macros.inc"),
+                    &dir.path().join("vendor/patched.rs")
+                ]
+            );
+            assert_eq!(rust.documentation.files.len(), 1);
+            assert_eq!(rust.vendored.files.len(), 0);
+            // only the `.rs` files below vendor/ are taken out of the vendored bucket
+            assert_eq!(reg.entries[1].stats.vendored.files.len(), 1);
+        }
+
+        #[test]
+        fn with_config_extends() {
+            let cuda = CfgLangEntry {
+                extends: Some("C".to_string()),
+                comments: None,
+                ..cfg_lang("CUDA", &["cu"], "//")
+            };
+            let dialect = CfgLangEntry {
+                extends: Some("CUDA".to_string()),
+                comments: None,
+                tab_width: Some(2),
+                decisions: Some(vec!["if".to_string()]),
+                ..cfg_lang("Dialect", &["cud"], "//")
+            };
+            let reg = LangRegistry::with_config(cfg(Path::new("."), vec![cuda, dialect])).unwrap();
+
+            let builtin = LangRegistry::with_builtins_langs(Path::new("."));
+            let c = &builtin.entries[1].spec;
+            let cuda = &reg.entries[0].spec;
+            assert_eq!(cuda.extensions, vec![OsString::from("cu")]);
+            assert_eq!(cuda.comments, c.comments);
+            assert_eq!(cuda.decisions, c.decisions);
+            assert_eq!(cuda.functions, c.functions);
+            // identifying fields are never inherited
+            assert!(cuda.modelines.is_empty());
+
+            let dialect = &reg.entries[1].spec;
+            assert_eq!(dialect.comments, c.comments);
+            assert_eq!(dialect.tab_width, 2);
+            assert_eq!(dialect.decisions, vec!["if".to_string()]);
+
+            let unknown = CfgLangEntry {
+                extends: Some("Nothing".to_string()),
+                ..cfg_lang("X", &["x"], "#")
+            };
+            let res = LangRegistry::with_config(cfg(Path::new("."), vec![unknown]));
+            assert!(
+                matches!(res.unwrap_err(), ConfigError::UnknownLanguage(name) if name == "Nothing")
+            );
+        }
+
+        #[test]
+        fn with_config_patch() {
+            let python = CfgLangEntry {
+                name: Some("Python".to_string()),
+                patch: Some(true),
+                extensions: Some(vec!["pyt".to_string()]),
+                comments: None,
+                max_line_width: Some(120),
+                ..cfg_lang("", &[], "")
+            };
+            let make = cfg_lang("Make", &["mk"], "#");
+            let make_patch = CfgLangEntry {
+                name: Some("Make".to_string()),
+                patch: Some(true),
+                comments: None,
+                filenames: Some(vec!["Makefile".to_string()]),
+                ..cfg_lang("", &[], "")
+            };
+            let reg =
+                LangRegistry::with_config(cfg(Path::new("."), vec![python, make, make_patch]))
+                    .unwrap();
+            // the patched Python takes the place of the built-in one
+            assert_eq!(
+                reg.entries
+                    .iter()
+                    .filter(|entry| entry.spec.name == "Python")
+                    .count(),
+                1
+            );
+
+            let python = &reg.entries[0].spec;
+            assert_eq!(python.name, "Python");
+            assert_eq!(
+                python.extensions,
+                ["py", "pyw", "pyi", "pyt"].map(OsString::from).to_vec()
+            );
+            assert_eq!(python.shebangs.len(), 3);
+            assert_eq!(python.max_line_width, Some(120));
+            assert!(python.functions.is_some());
+
+            let make = &reg.entries[1].spec;
+            assert_eq!(make.extensions, vec![OsString::from("mk")]);
+            assert_eq!(make.filenames, vec![OsString::from("Makefile")]);
+        }
+        #[test]
+        fn with_config_layers_builtins() {
+            let dir = tempdir().unwrap();
+            std::fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+            std::fs::write(dir.path().join("util.py"), "x = 1\n").unwrap();
+            std::fs::write(dir.path().join("Dockerfile"), "FROM scratch\n").unwrap();
+            std::fs::write(dir.path().join("lib.h"), "int f(void);\n").unwrap();
+
+            let rust = cfg_lang("Rust", &["rs"], "//");
+            let header = cfg_lang("Header", &["h"], "//");
+            let docker = CfgLangEntry {
+                filenames: Some(vec!["Dockerfile".to_string()]),
+                ..cfg_lang("Container", &[], "#")
+            };
+            let mut reg =
+                LangRegistry::with_config(cfg(dir.path(), vec![rust, header, docker])).unwrap();
+            reg.update_stats().unwrap();
+            // user languages come first, the built-in Rust is replaced by name
+            assert_eq!(reg.entries[0].spec.name, "Rust");
+            assert_eq!(reg.entries[0].spec.functions, None);
+            assert_eq!(
+                reg.entries
+                    .iter()
+                    .filter(|entry| entry.spec.name == "Rust")
+                    .count(),
+                1
+            );
+            // claimed extensions and file names are taken from the built-ins
+            assert_eq!(reg.entries[1].stats.files.len(), 1);
+            assert_eq!(reg.entries[2].stats.files.len(), 1);
+            let c = reg.find_language("C").unwrap();
+            assert_eq!(reg.entries[c.0].spec.extensions, vec![OsString::from("c")]);
+            assert!(reg.entries[c.0].spec.default_for.is_empty());
+            let docker = reg.find_language("Dockerfile").unwrap();
+            assert!(
+                !reg.entries[docker.0]
+                    .spec
+                    .filenames
+                    .contains(&OsString::from("Dockerfile"))
+            );
+            // unclaimed built-ins still count
+            let python = reg.find_language("Python").unwrap();
+            assert_eq!(reg.entries[python.0].stats.files.len(), 1);
+        }
+
+        #[test]
+        fn with_config_replace_builtins() {
+            let config = Config {
+                replace_builtins: Some(true),
+                ..cfg(Path::new("."), vec![cfg_lang("Rust", &["rs"], "//")])
+            };
+            let reg = LangRegistry::with_config(config).unwrap();
+            assert_eq!(reg.entries.len(), 1);
+            assert!(reg.find_language("Python").is_none());
+
+            // extends and patch still reach the built-ins
+            let python = CfgLangEntry {
+                name: Some("Python".to_string()),
+                patch: Some(true),
+                comments: None,
+                ..cfg_lang("", &[], "")
+            };
+            let config = Config {
+                replace_builtins: Some(true),
+                ..cfg(Path::new("."), vec![python])
+            };
+            let reg = LangRegistry::with_config(config).unwrap();
+            assert_eq!(reg.entries.len(), 1);
+            assert_eq!(reg.entries[0].spec.shebangs.len(), 3);
+        }
+
+        #[test]
+        fn with_config_conflicts() {
+            let res = LangRegistry::with_config(cfg(
+                Path::new("."),
+                vec![
+                    cfg_lang("Rust", &["rs"], "//"),
+                    cfg_lang("Rust", &["rlib"], "//"),
+                ],
+            ));
+            assert!(
+                matches!(res.unwrap_err(), ConfigError::LanguageConflict(msg) if msg == "Rust: language Rust is already defined")
+            );
+
+            let make = |name: &str| CfgLangEntry {
+                filenames: Some(vec!["Makefile".to_string()]),
+                ..cfg_lang(name, &[], "#")
+            };
+            let res = LangRegistry::with_config(cfg(
+                Path::new("."),
+                vec![make("Make"), make("BSD Make")],
+            ));
+            assert!(
+                matches!(res.unwrap_err(), ConfigError::LanguageConflict(msg) if msg == "BSD Make: file name Makefile is already claimed by Make")
+            );
+        }
+        #[test]
+        fn update_stats_categories() {
+            let dir = tempdir().unwrap();
+            std::fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+            std::fs::write(dir.path().join("build.sh"), "make\n").unwrap();
+            std::fs::write(dir.path().join("ci.yml"), "a: 1\nb: 2\n").unwrap();
+            std::fs::write(dir.path().join("data.json"), "{}\n").unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.update_stats().unwrap();
+            let report = reg.report(&ReportOptions::default());
+            let categories: Vec<(Category, usize, u64)> = report
+                .categories
+                .iter()
+                .map(|category| (category.category, category.languages.len(), category.loc))
+                .collect();
+            assert_eq!(
+                categories,
+                [
+                    (Category::Programming, 2, 2),
+                    (Category::Data, 1, 1),
+                    (Category::Config, 1, 2),
+                ]
+            );
+
+            reg.set_categories(vec![Category::Programming]);
+            reg.update_stats().unwrap();
+            let report = reg.report(&ReportOptions::default());
+            assert_eq!(report.categories.len(), 1);
+            assert_eq!(report.categories[0].files, 2);
+            assert_eq!(reg.estimate().kloc, 0.002);
+        }
+        #[test]
+        fn language_listing_sources() {
+            let make = CfgLangEntry {
+                filenames: Some(vec!["Makefile".to_string(), "*.mk".to_string()]),
+                ..cfg_lang("Make", &[], "#")
+            };
+            let python = CfgLangEntry {
+                name: Some("Python".to_string()),
+                patch: Some(true),
+                comments: None,
+                tab_width: Some(2),
+                ..cfg_lang("", &[], "")
+            };
+            let config = Config {
+                path: PathBuf::from("team.toml"),
+                ..cfg(Path::new("."), vec![make, python])
+            };
+            let reg = LangRegistry::with_config(config).unwrap();
+            let listing = reg.language_listing();
+            assert_eq!(listing.languages.len(), reg.entries.len());
+
+            let make = &listing.languages[0];
+            assert_eq!(make.name, "Make");
+            assert_eq!(make.source, "team.toml");
+            assert_eq!(make.filenames, ["Makefile", "*.mk"]);
+            assert!(make.extensions.is_empty());
+            assert_eq!(listing.languages[1].name, "Python");
+            assert_eq!(
+                listing.languages[1].source,
+                "built-in, patched in team.toml"
+            );
+
+            let rust = listing
+                .languages
+                .iter()
+                .find(|language| language.name == "Rust")
+                .unwrap();
+            assert_eq!(rust.source, "built-in");
+            assert_eq!(rust.line_comments, ["//", "///", "//!"]);
+            assert_eq!(
+                rust.block_comment.map(|block| block.open.as_str()),
+                Some("/*")
+            );
+        }
+        #[test]
+        fn with_config_patch_keeps_shared_extensions() {
+            let dir = tempdir().unwrap();
+            std::fs::write(dir.path().join("a.h"), "int f(void);\n").unwrap();
+            std::fs::write(dir.path().join("b.h"), "namespace x {\nclass Y {};\n}\n").unwrap();
+            let c = CfgLangEntry {
+                name: Some("C".to_string()),
+                patch: Some(true),
+                comments: None,
+                max_line_width: Some(120),
+                ..cfg_lang("", &[], "")
+            };
+            let mut reg = LangRegistry::with_config(cfg(dir.path(), vec![c])).unwrap();
+            reg.update_stats().unwrap();
+
+            let cpp = reg.find_language("C++").unwrap();
+            assert!(
+                reg.entries[cpp.0]
+                    .spec
+                    .extensions
+                    .contains(&OsString::from("h"))
+            );
+            assert_eq!(
+                reg.entries[cpp.0].stats.files.keys().collect::<Vec<_>>(),
+                vec![&dir.path().join("b.h")]
+            );
+            let c = reg.find_language("C").unwrap();
+            assert_eq!(reg.entries[c.0].spec.max_line_width, Some(120));
+            assert_eq!(
+                reg.entries[c.0].stats.files.keys().collect::<Vec<_>>(),
+                vec![&dir.path().join("a.h")]
+            );
+        }
+    }
+    mod builtin_languages {
+        use crate::registry::{LangEntry, LangRegistry, builtin_languages};
+        use std::ffi::OsString;
+        use tempfile::tempdir;
+
+        #[test]
+        fn every_entry_parses() {
+            let languages = builtin_languages();
+            assert!(languages.len() >= 150);
+            for language in languages {
+                let name = language.name.clone();
+                assert!(language.category.is_some(), "{:?} has no category", name);
+                let res: Result<LangEntry, _> = language.try_into();
+                assert!(res.is_ok(), "{:?}: {:?}", name, res.err());
+            }
+        }
+
+        #[test]
+        fn shared_extensions_are_intended() {
+            // building the registry already rejects shared names, file names, shebangs and modelines
+            let reg = LangRegistry::with_builtins_langs(std::path::Path::new("."));
+            let mut shared: Vec<(&OsString, &Vec<_>)> = reg
+                .map_ext_id
+                .iter()
+                .filter(|(_, ids)| ids.len() > 1)
+                .collect();
+            shared.sort_by(|a, b| a.0.cmp(b.0));
+            for (ext, ids) in shared {
+                // the fallback is configured, every other claimant says how to recognise it
+                assert!(
+                    reg.get_spec(ids[0]).default_for.contains(ext),
+                    "no default for {:?}",
+                    ext
+                );
+                for id in &ids[1..] {
+                    let spec = reg.get_spec(*id);
+                    assert!(
+                        !spec.heuristics.is_empty(),
+                        "{} shares {:?} without heuristics",
+                        spec.name,
+                        ext
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn headers_by_content() {
+            let dir = tempdir().unwrap();
+            std::fs::write(dir.path().join("a.h"), "int add(int a, int b);\n").unwrap();
+            std::fs::write(dir.path().join("b.h"), "namespace b {\nclass B {};\n}\n").unwrap();
+            std::fs::write(
+                dir.path().join("c.h"),
+                "#import <Foundation/Foundation.h>\n",
+            )
+            .unwrap();
+            std::fs::write(dir.path().join("setup.pl"), "use strict;\nmy $x = 1;\n").unwrap();
+            std::fs::write(
+                dir.path().join("family.pl"),
+                "parent(tom, bob).\nancestor(X, Y) :- parent(X, Y).\n",
+            )
+            .unwrap();
+
+            let mut reg = LangRegistry::with_builtins_langs(dir.path());
+            reg.update_stats().unwrap();
+            let files = |name: &str| {
+                let entry = reg.entries.iter().find(|e| e.spec.name == name).unwrap();
+                let mut files: Vec<_> = entry
+                    .stats
+                    .files
+                    .keys()
+                    .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+                    .collect();
+                files.sort();
+                files
+            };
+            assert_eq!(files("C"), vec!["a.h"]);
+            assert_eq!(files("C++"), vec!["b.h"]);
+            assert_eq!(files("Objective-C"), vec!["c.h"]);
+            assert_eq!(files("Perl"), vec!["setup.pl"]);
+            assert_eq!(files("Prolog"), vec!["family.pl"]);
+        }
     }
 }
@@ -0,0 +1,501 @@
+use crate::analysis::{Function, LongLine, Marker, Volumes};
+use crate::cocomo::{Estimate, Model};
+use crate::dedup::{DuplicateFiles, DuplicateKind};
+use crate::duplicates::CloneGroup;
+use crate::registry::{Block, Category};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Optional sections printed after the per-language summary.
+#[derive(Debug, Default)]
+pub struct ReportOptions {
+    /// Metrics of every production file.
+    pub per_file: bool,
+    /// The N most complex production files.
+    pub top_complex: Option<usize>,
+    /// Production functions longer than N code lines.
+    pub long_functions: Option<u64>,
+    /// Production lines wider than the limit of their language.
+    pub long_lines: bool,
+    /// Comment markers such as `TODO`, by path and line.
+    pub markers: bool,
+    /// License totals and files without a license header, third-party code included.
+    pub licenses: bool,
+    /// Duplicated production code, in runs of at least N code lines.
+    pub duplicates: Option<usize>,
+    /// Files reached through several links or copied byte for byte.
+    pub duplicate_files: bool,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Report<'a> {
+    pub(crate) dir: &'a Path,
+    pub(crate) categories: Vec<CategoryReport<'a>>,
+    pub(crate) cocomo: Estimate,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) files: Option<Vec<FileReport<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) most_complex: Option<Vec<FileReport<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) long_functions: Option<LongFunctions<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) long_lines: Option<Vec<LongLineReport<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) markers: Option<Vec<MarkerReport<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) licenses: Option<Licenses<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) duplicates: Option<Duplicates<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) duplicate_files: Option<DuplicateFilesReport<'a>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct BucketReport {
+    pub(crate) files: usize,
+    pub(crate) loc: u64,
+}
+
+/// Languages of one category, with the subtotals of their first-party files.
+#[derive(Serialize)]
+pub(crate) struct CategoryReport<'a> {
+    pub(crate) category: Category,
+    pub(crate) files: usize,
+    pub(crate) loc: u64,
+    pub(crate) complexity: u64,
+    pub(crate) test: BucketReport,
+    pub(crate) languages: Vec<LanguageReport<'a>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct LanguageReport<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) files: usize,
+    pub(crate) loc: u64,
+    pub(crate) complexity: u64,
+    pub(crate) max_depth: u64,
+    pub(crate) mean_depth: f64,
+    pub(crate) functions: usize,
+    pub(crate) mean_function_length: f64,
+    pub(crate) max_function_length: u64,
+    pub(crate) max_line_length: u64,
+    pub(crate) mean_line_length: f64,
+    pub(crate) p95_line_length: u64,
+    pub(crate) vendored: BucketReport,
+    pub(crate) generated: BucketReport,
+    pub(crate) documentation: BucketReport,
+    pub(crate) test: BucketReport,
+    pub(crate) test_ratio: Option<f64>,
+    pub(crate) markers: BTreeMap<&'a str, usize>,
+    pub(crate) volume: Volumes,
+}
+
+#[derive(Serialize)]
+pub(crate) struct FileReport<'a> {
+    pub(crate) path: &'a Path,
+    pub(crate) language: &'a str,
+    pub(crate) loc: u64,
+    pub(crate) complexity: u64,
+    pub(crate) functions: usize,
+    pub(crate) max_depth: u64,
+    pub(crate) mean_depth: f64,
+    pub(crate) max_line_length: u64,
+    pub(crate) mean_line_length: f64,
+    pub(crate) p95_line_length: u64,
+    pub(crate) markers: BTreeMap<&'a str, usize>,
+    pub(crate) volume: &'a Volumes,
+}
+
+#[derive(Serialize)]
+pub(crate) struct LongFunctions<'a> {
+    pub(crate) threshold: u64,
+    pub(crate) functions: Vec<FunctionReport<'a>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct FunctionReport<'a> {
+    pub(crate) path: &'a Path,
+    pub(crate) language: &'a str,
+    #[serde(flatten)]
+    pub(crate) function: &'a Function,
+}
+
+#[derive(Serialize)]
+pub(crate) struct LongLineReport<'a> {
+    pub(crate) path: &'a Path,
+    pub(crate) language: &'a str,
+    #[serde(flatten)]
+    pub(crate) line: &'a LongLine,
+    pub(crate) limit: u64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct MarkerReport<'a> {
+    pub(crate) path: &'a Path,
+    pub(crate) language: &'a str,
+    #[serde(flatten)]
+    pub(crate) marker: &'a Marker,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Licenses<'a> {
+    pub(crate) totals: Vec<LicenseTotal<'a>>,
+    pub(crate) unlicensed: Vec<UnlicensedFile<'a>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct LicenseTotal<'a> {
+    pub(crate) license: &'a str,
+    pub(crate) files: usize,
+    pub(crate) loc: u64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct UnlicensedFile<'a> {
+    pub(crate) path: &'a Path,
+    pub(crate) language: &'a str,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Duplicates<'a> {
+    pub(crate) min_lines: usize,
+    pub(crate) languages: Vec<LanguageDuplication<'a>>,
+    /// The largest clone groups.
+    pub(crate) groups: Vec<CloneGroup<'a>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct LanguageDuplication<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) loc: u64,
+    pub(crate) duplicated: usize,
+    pub(crate) percent: f64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DuplicateFilesReport<'a> {
+    /// Whether only the first path of each group was counted.
+    pub(crate) counted_once: bool,
+    pub(crate) groups: &'a [DuplicateFiles],
+}
+
+/// Languages in effect once the configuration is loaded, in the order they are matched.
+#[derive(Serialize)]
+pub(crate) struct LanguageListing<'a> {
+    pub(crate) languages: Vec<LanguageDefinition<'a>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct LanguageDefinition<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) category: Category,
+    pub(crate) extensions: Vec<Cow<'a, str>>,
+    /// Exact file names, then file name globs.
+    pub(crate) filenames: Vec<Cow<'a, str>>,
+    pub(crate) line_comments: &'a [String],
+    pub(crate) block_comment: Option<&'a Block>,
+    /// `built-in`, or the path of the configuration file.
+    pub(crate) source: String,
+}
+
+impl LanguageListing<'_> {
+    /// One row per language, columns padded to their widest cell.
+    pub(crate) fn print_text(&self) {
+        let header = [
+            "NAME",
+            "CATEGORY",
+            "SOURCE",
+            "COMMENTS",
+            "EXTENSIONS",
+            "FILENAMES",
+        ];
+        let rows: Vec<[String; 6]> = std::iter::once(header.map(String::from))
+            .chain(self.languages.iter().map(LanguageDefinition::row))
+            .collect();
+        let mut widths = [0; 6];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        for row in &rows {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell))
+                .collect();
+            println!("{}", cells.join("  ").trim_end());
+        }
+    }
+
+    pub(crate) fn print_json(&self) -> Result<(), std::io::Error> {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), self)?;
+        println!();
+        Ok(())
+    }
+}
+
+impl LanguageDefinition<'_> {
+    fn row(&self) -> [String; 6] {
+        let list = |items: &[Cow<str>]| {
+            if items.is_empty() {
+                "-".to_string()
+            } else {
+                items.join(" ")
+            }
+        };
+        let mut comments = self.line_comments.to_vec();
+        if let Some(block) = self.block_comment {
+            comments.push(format!("{} {}", block.open, block.close));
+        }
+        [
+            self.name.to_string(),
+            self.category.name(),
+            self.source.clone(),
+            if comments.is_empty() {
+                "-".to_string()
+            } else {
+                comments.join(" ")
+            },
+            list(&self.extensions),
+            list(&self.filenames),
+        ]
+    }
+}
+
+impl Report<'_> {
+    pub(crate) fn print_text(&self) {
+        println!("STATS for directory: {}", self.dir.display());
+        for category in &self.categories {
+            category.print_text();
+        }
+        self.cocomo.print_text();
+        if let Some(files) = &self.files {
+            println!("FILES:");
+            for file in files {
+                file.print_text();
+            }
+        }
+        if let Some(files) = &self.most_complex {
+            println!("MOST COMPLEX files:");
+            for file in files {
+                println!(
+                    "{} ({}) complexity: {}",
+                    file.path.display(),
+                    file.language,
+                    file.complexity
+                );
+            }
+        }
+        if let Some(long) = &self.long_functions {
+            println!("LONG FUNCTIONS (more than {} code lines):", long.threshold);
+            for f in &long.functions {
+                println!(
+                    "{}:{} {} ({} lines)",
+                    f.path.display(),
+                    f.function.line,
+                    f.function.name,
+                    f.function.length
+                );
+            }
+        }
+        if let Some(lines) = &self.long_lines {
+            println!("LONG LINES:");
+            for l in lines {
+                println!(
+                    "{}:{} length: {} (limit {})",
+                    l.path.display(),
+                    l.line.line,
+                    l.line.length,
+                    l.limit
+                );
+            }
+        }
+        if let Some(markers) = &self.markers {
+            println!("MARKERS:");
+            for m in markers {
+                println!("{}:{} {}", m.path.display(), m.marker.line, m.marker.text);
+            }
+        }
+        if let Some(licenses) = &self.licenses {
+            println!("LICENSES:");
+            for total in &licenses.totals {
+                println!(
+                    "{}, files: {} loc: {}",
+                    total.license, total.files, total.loc
+                );
+            }
+            println!("FILES WITHOUT LICENSE HEADER:");
+            for file in &licenses.unlicensed {
+                println!("{} ({})", file.path.display(), file.language);
+            }
+        }
+        if let Some(duplicates) = &self.duplicates {
+            println!("DUPLICATES (at least {} code lines):", duplicates.min_lines);
+            for language in &duplicates.languages {
+                println!(
+                    "{}, loc: {} duplicated: {} ({:.1}%)",
+                    language.name, language.loc, language.duplicated, language.percent
+                );
+            }
+            for group in &duplicates.groups {
+                println!("{} lines, {} copies:", group.lines, group.locations.len());
+                for l in &group.locations {
+                    println!("    {}:{}-{}", l.path.display(), l.start, l.end);
+                }
+            }
+        }
+        if let Some(duplicates) = &self.duplicate_files {
+            if duplicates.counted_once {
+                println!("DUPLICATE FILES (counted once):");
+            } else {
+                println!("DUPLICATE FILES:");
+            }
+            for group in duplicates.groups {
+                let kind = match group.kind {
+                    DuplicateKind::Linked => "linked",
+                    DuplicateKind::Identical => "identical",
+                };
+                println!("{}:", kind);
+                for path in &group.paths {
+                    println!("    {}", path.display());
+                }
+            }
+        }
+    }
+
+    pub(crate) fn print_json(&self) -> Result<(), std::io::Error> {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), self)?;
+        println!();
+        Ok(())
+    }
+}
+
+impl Estimate {
+    fn print_text(&self) {
+        if self.kloc == 0.0 {
+            return;
+        }
+        let model = match self.model {
+            Model::Basic => "basic",
+            Model::Intermediate => "intermediate",
+        };
+        println!(
+            "COCOMO ({}), kloc: {:.1} effort: {:.1} person-months schedule: {:.1} months \
+             developers: {:.1} cost: {:.0}",
+            model, self.kloc, self.effort, self.schedule, self.developers, self.cost
+        );
+    }
+}
+
+impl CategoryReport<'_> {
+    fn print_text(&self) {
+        print!(
+            "{}, files: {} loc: {} complexity: {}",
+            self.category.name().to_uppercase(),
+            self.files,
+            self.loc,
+            self.complexity
+        );
+        if self.test.files > 0 {
+            print!(" test files: {} loc: {}", self.test.files, self.test.loc);
+        }
+        println!();
+        for language in &self.languages {
+            language.print_text();
+        }
+    }
+}
+
+impl LanguageReport<'_> {
+    fn print_text(&self) {
+        println!(
+            "{}, files: {} loc: {} complexity: {}",
+            self.name, self.files, self.loc, self.complexity
+        );
+        for (label, bucket) in [
+            ("vendored", &self.vendored),
+            ("generated", &self.generated),
+            ("documentation", &self.documentation),
+        ] {
+            if bucket.files > 0 {
+                println!("    {} files: {} loc: {}", label, bucket.files, bucket.loc);
+            }
+        }
+        if self.loc > 0 {
+            println!(
+                "    nesting max depth: {} mean depth: {:.2}",
+                self.max_depth, self.mean_depth
+            );
+            println!(
+                "    line length max: {} mean: {:.1} p95: {}",
+                self.max_line_length, self.mean_line_length, self.p95_line_length
+            );
+        }
+        if self.files > 0 {
+            print_volume(&self.volume);
+        }
+        if self.functions > 0 {
+            println!(
+                "    functions: {} mean length: {:.1} max length: {}",
+                self.functions, self.mean_function_length, self.max_function_length
+            );
+        }
+        if !self.markers.is_empty() {
+            println!("    markers: {}", format_counts(&self.markers));
+        }
+        if self.test.files > 0 {
+            print!("    test files: {} loc: {}", self.test.files, self.test.loc);
+            match self.test_ratio {
+                Some(ratio) => println!(" test/code ratio: {:.2}", ratio),
+                None => println!(),
+            }
+        }
+    }
+}
+
+impl FileReport<'_> {
+    fn print_text(&self) {
+        println!(
+            "{} ({}) loc: {} complexity: {} functions: {} max depth: {} mean depth: {:.2} \
+             max line: {} p95 line: {}",
+            self.path.display(),
+            self.language,
+            self.loc,
+            self.complexity,
+            self.functions,
+            self.max_depth,
+            self.mean_depth,
+            self.max_line_length,
+            self.p95_line_length
+        );
+        print_volume(self.volume);
+        if !self.markers.is_empty() {
+            println!("    markers: {}", format_counts(&self.markers));
+        }
+    }
+}
+
+fn print_volume(volume: &Volumes) {
+    let total = volume.total();
+    println!(
+        "    bytes: {} (code: {} comment: {} blank: {}) chars: {} tokens: ~{} (code: ~{})",
+        total.bytes,
+        volume.code.bytes,
+        volume.comment.bytes,
+        volume.blank.bytes,
+        total.chars,
+        total.tokens,
+        volume.code.tokens
+    );
+}
+
+fn format_counts(counts: &BTreeMap<&str, usize>) -> String {
+    counts
+        .iter()
+        .map(|(name, count)| format!("{}: {}", name, count))
+        .collect::<Vec<_>>()
+        .join(" ")
+}